@@ -0,0 +1,187 @@
+use std::{
+    fs::File,
+    io::{self, BufReader, BufWriter},
+    path::{Path, PathBuf},
+};
+
+use crate::simulation::Snapshot;
+
+/// Playback speed of the time-lapse, independent of how many ticks lie between two frames
+const TIMELAPSE_FRAMES_PER_SECOND: u16 = 10;
+/// Quantization speed of the GIF encoder, from 1 (best quality) to 30 (fastest)
+const GIF_QUANTIZATION_SPEED: i32 = 10;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TimelapseFormat {
+    Gif,
+    Apng,
+}
+
+impl TimelapseFormat {
+    fn extension(&self) -> &'static str {
+        match self {
+            TimelapseFormat::Gif => "gif",
+            TimelapseFormat::Apng => "png",
+        }
+    }
+}
+
+/// Writes a PNG of the board every `interval` ticks, optionally collecting them into a time-lapse.
+pub struct FrameExporter {
+    directory: PathBuf,
+    interval: u64,
+    pheromone_overlay: Option<u8>,
+    timelapse: Option<Timelapse>,
+    frames: Vec<PathBuf>,
+    /// Tick of the latest frame, a paused world keeps handing in the same snapshot
+    last_tick: Option<u64>,
+}
+
+enum Timelapse {
+    /// GIF frames are appended as they come in, the encoder is created with the first one
+    Gif(Option<gif::Encoder<BufWriter<File>>>),
+    /// APNG needs the frame count up front, so the PNG frames are read back once the export is finished
+    Apng,
+}
+
+impl FrameExporter {
+    pub fn new(
+        directory: impl Into<PathBuf>,
+        interval: u64,
+        timelapse: Option<TimelapseFormat>,
+    ) -> io::Result<Self> {
+        let directory = directory.into();
+        std::fs::create_dir_all(&directory)?;
+        Ok(Self {
+            directory,
+            interval: interval.max(1),
+            pheromone_overlay: None,
+            timelapse: timelapse.map(|format| match format {
+                TimelapseFormat::Gif => Timelapse::Gif(None),
+                TimelapseFormat::Apng => Timelapse::Apng,
+            }),
+            frames: Vec::new(),
+            last_tick: None,
+        })
+    }
+
+    pub fn set_pheromone_overlay(&mut self, pheromone_overlay: Option<u8>) {
+        self.pheromone_overlay = pheromone_overlay;
+    }
+
+    pub fn frame_count(&self) -> usize {
+        self.frames.len()
+    }
+
+    /// Whether the snapshot of this tick would be exported, lets callers skip building it otherwise.
+    pub fn is_due(&self, tick: u64) -> bool {
+        tick.is_multiple_of(self.interval) && self.last_tick != Some(tick)
+    }
+
+    /// Exports the snapshot if it falls on the interval and its tick was not exported yet.
+    pub fn capture(&mut self, snapshot: &Snapshot) -> io::Result<()> {
+        if !self.is_due(snapshot.current_tick()) {
+            return Ok(());
+        }
+        self.last_tick = Some(snapshot.current_tick());
+        let width = snapshot.width() as u32;
+        let height = snapshot.height() as u32;
+        let mut pixels = snapshot.render(self.pheromone_overlay);
+
+        let path = self
+            .directory
+            .join(format!("frame_{:08}.png", snapshot.current_tick()));
+        write_png(&path, width, height, &pixels)?;
+        self.frames.push(path);
+
+        let gif_path = self.timelapse_path(TimelapseFormat::Gif);
+        if let Some(Timelapse::Gif(encoder)) = &mut self.timelapse {
+            let encoder = match encoder {
+                Some(encoder) => encoder,
+                None => {
+                    let file = BufWriter::new(File::create(gif_path)?);
+                    let mut new_encoder = gif::Encoder::new(file, width as u16, height as u16, &[])
+                        .map_err(io::Error::other)?;
+                    new_encoder
+                        .set_repeat(gif::Repeat::Infinite)
+                        .map_err(io::Error::other)?;
+                    encoder.insert(new_encoder)
+                }
+            };
+            let mut frame = gif::Frame::from_rgba_speed(
+                width as u16,
+                height as u16,
+                &mut pixels,
+                GIF_QUANTIZATION_SPEED,
+            );
+            frame.delay = 100 / TIMELAPSE_FRAMES_PER_SECOND;
+            encoder.write_frame(&frame).map_err(io::Error::other)?;
+        }
+        Ok(())
+    }
+
+    /// Completes the time-lapse, if any, and returns where it was written.
+    pub fn finish(self) -> io::Result<Option<PathBuf>> {
+        let gif_path = self.timelapse_path(TimelapseFormat::Gif);
+        match self.timelapse {
+            None | Some(Timelapse::Gif(None)) => Ok(None),
+            Some(Timelapse::Gif(Some(encoder))) => {
+                encoder.into_inner().map_err(io::Error::other)?;
+                Ok(Some(gif_path))
+            }
+            Some(Timelapse::Apng) if self.frames.is_empty() => Ok(None),
+            Some(Timelapse::Apng) => {
+                let path = self.timelapse_path(TimelapseFormat::Apng);
+                write_apng(&path, &self.frames)?;
+                Ok(Some(path))
+            }
+        }
+    }
+
+    fn timelapse_path(&self, format: TimelapseFormat) -> PathBuf {
+        self.directory
+            .join(format!("timelapse.{}", format.extension()))
+    }
+}
+
+fn write_png(path: &Path, width: u32, height: u32, pixels: &[u8]) -> io::Result<()> {
+    let file = BufWriter::new(File::create(path)?);
+    let mut encoder = png::Encoder::new(file, width, height);
+    encoder.set_color(png::ColorType::Rgba);
+    encoder.set_depth(png::BitDepth::Eight);
+    let mut writer = encoder.write_header()?;
+    writer.write_image_data(pixels)?;
+    writer.finish()?;
+    Ok(())
+}
+
+fn read_png(path: &Path) -> io::Result<(u32, u32, Vec<u8>)> {
+    let decoder = png::Decoder::new(BufReader::new(File::open(path)?));
+    let mut reader = decoder.read_info()?;
+    let size = reader
+        .output_buffer_size()
+        .ok_or_else(|| io::Error::other(format!("{} is too large", path.display())))?;
+    let mut pixels = vec![0; size];
+    let info = reader.next_frame(&mut pixels)?;
+    pixels.truncate(info.buffer_size());
+    Ok((info.width, info.height, pixels))
+}
+
+/// Combines the frames into an animated PNG, all of them must have the same size.
+fn write_apng(path: &Path, frames: &[PathBuf]) -> io::Result<()> {
+    let (width, height, first) = read_png(&frames[0])?;
+    let file = BufWriter::new(File::create(path)?);
+    let mut encoder = png::Encoder::new(file, width, height);
+    encoder.set_color(png::ColorType::Rgba);
+    encoder.set_depth(png::BitDepth::Eight);
+    encoder.set_animated(frames.len() as u32, 0)?;
+    encoder.set_frame_delay(1, TIMELAPSE_FRAMES_PER_SECOND)?;
+    let mut writer = encoder.write_header()?;
+    writer.write_image_data(&first)?;
+    for frame in &frames[1..] {
+        let (_, _, pixels) = read_png(frame)?;
+        writer.write_image_data(&pixels)?;
+    }
+    writer.finish()?;
+    Ok(())
+}
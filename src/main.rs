@@ -1,9 +1,21 @@
+#![cfg_attr(all(test, feature = "bench"), feature(test))]
+
 use crate::ui::UI;
 
+pub mod export;
 pub mod simulation;
 pub mod ui;
 
 fn main() {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    if args.iter().any(|arg| arg == "--headless") {
+        if let Err(err) = headless::run(&args) {
+            eprintln!("{err}");
+            std::process::exit(1);
+        }
+        return;
+    }
+
     iced::application(UI::boot, UI::update, UI::view)
         .subscription(UI::subscription)
         .run()
@@ -17,3 +29,388 @@ fn main() {
     // let mut world = World::new(1000, 1000, Tile::Lava, settings);
     // println!("World created!");
 }
+
+mod headless {
+    use std::{
+        sync::{Arc, Mutex},
+        time::Instant,
+    };
+
+    use crate::{
+        export::{FrameExporter, TimelapseFormat},
+        simulation::{
+            Tile, World, WorldSettings,
+            catastrophe::{Catastrophe, CatastropheKind, ScheduledCatastrophe},
+            clock::{Cycle, WorldClock},
+            food::{BiomeRates, FoodGrowth, FoodSource, GrowthModel},
+            neural_network::compare_evaluators,
+            replay::{Replay, ReplayPlayer, ReplayRecorder},
+            spawn::{GenomeLibrary, SpawnRegion, SpawnStrategy},
+        },
+    };
+
+    /// Genomes with the most carriers that `--save-library` keeps
+    const LIBRARY_SIZE: usize = 10;
+
+    /// Runs the simulation without a window, exporting frames along the way.
+    ///
+    /// `--headless [--ticks N] [--frames-every N] [--output DIR] [--timelapse gif|apng] [--seed N]
+    /// [--spawn STRATEGY] [--library FILE [--library-copies N]] [--save-library FILE]
+    /// [--food MODEL] [--food-season PERIOD,AMPLITUDE] [--food-sources X,Y,RADIUS;...] [--biomes SIZE]
+    /// [--biome-rates MEADOW,FOREST,DESERT] [--seasons CYCLE] [--days CYCLE] [--lava-heat N] [--catastrophes TICK:KIND:X,Y,RADIUS;...]`
+    ///
+    /// See `spawn_strategy` for the strategies, `food_growth` for the food options, `cycle` for the cycles and
+    /// `catastrophes` for the catastrophes. `--library` seeds the world from a genome library,
+    /// `--save-library` saves the most common genomes at the end.
+    ///
+    /// With `--bench-threads 1,2,4,8` it benchmarks applying the ticks with each number of threads instead.
+    /// With `--bench-brains ROUNDS` it runs the ticks, then compares the ways of evaluating the brains.
+    pub fn run(args: &[String]) -> Result<(), String> {
+        let option = |name: &str| {
+            args.iter()
+                .position(|arg| arg == name)
+                .and_then(|index| args.get(index + 1))
+        };
+        let number = |name: &str, default: u64| match option(name) {
+            Some(value) => value
+                .parse()
+                .map_err(|_| format!("{name} expects a number, got {value}")),
+            None => Ok(default),
+        };
+
+        let ticks = number("--ticks", 10_000)?;
+        let interval = number("--frames-every", 100)?;
+        let output = option("--output").map_or("frames", String::as_str);
+        let timelapse = match option("--timelapse").map(String::as_str) {
+            None => None,
+            Some("gif") => Some(TimelapseFormat::Gif),
+            Some("apng") => Some(TimelapseFormat::Apng),
+            Some(format) => return Err(format!("--timelapse expects gif or apng, got {format}")),
+        };
+        let settings = WorldSettings {
+            seed: number("--seed", fastrand::u64(..))?,
+            spawn_strategy: option("--spawn")
+                .map(|strategy| spawn_strategy(strategy))
+                .transpose()?
+                .unwrap_or_default(),
+            food_growth: food_growth(
+                option("--food"),
+                option("--food-sources"),
+                option("--biome-rates"),
+            )?,
+            biome_size: number("--biomes", 0)? as usize,
+            clock: WorldClock {
+                seasons: seasons(option("--seasons"), option("--food-season"))?,
+                days: option("--days")
+                    .map(|value| cycle("--days", value))
+                    .transpose()?,
+            },
+            lava_heat: number("--lava-heat", 0)? as u16,
+            catastrophes: option("--catastrophes")
+                .map(|value| catastrophes(value))
+                .transpose()?
+                .unwrap_or_default(),
+            ..WorldSettings::default()
+        };
+
+        if let Some(threads) = option("--bench-threads") {
+            return bench_threads(threads, ticks, settings);
+        }
+        if option("--bench-brains").is_some() {
+            bench_brains(number("--bench-brains", 1)? as u32, ticks, settings);
+            return Ok(());
+        }
+
+        let mut exporter = FrameExporter::new(output, interval, timelapse)
+            .map_err(|err| format!("Failed to create {output}: {err}"))?;
+        let mut world = World::new(1000, 1000, Tile::Lava, settings);
+        if let Some(path) = option("--library") {
+            let library =
+                GenomeLibrary::load(path).map_err(|err| format!("Failed to read {path}: {err}"))?;
+            world.seed_from_library(&library, number("--library-copies", 20)? as usize);
+        }
+        for _ in 0..ticks {
+            world.tick();
+            if exporter.is_due(world.current_tick())
+                && let Err(err) = exporter.capture(&world.snapshot())
+            {
+                eprintln!("Failed to export tick {}: {err}", world.current_tick());
+            }
+        }
+
+        if let Some(path) = option("--save-library")
+            && let Err(err) = world.genome_library(LIBRARY_SIZE).save(path)
+        {
+            eprintln!("Failed to write {path}: {err}");
+        }
+
+        println!("Exported {} frames to {output}", exporter.frame_count());
+        match exporter.finish() {
+            Ok(Some(path)) => println!("Time-lapse written to {}", path.display()),
+            Ok(None) => (),
+            Err(err) => eprintln!("Failed to write the time-lapse: {err}"),
+        }
+        Ok(())
+    }
+
+    /// Parses `continuous`, `initial:TICKS`, `floor:CREATURES`, `mutants:GENOMES` or
+    /// `regions:X,Y,WIDTH,HEIGHT[;X,Y,WIDTH,HEIGHT...]`.
+    fn spawn_strategy(strategy: &str) -> Result<SpawnStrategy, String> {
+        let (kind, argument) = strategy.split_once(':').unwrap_or((strategy, ""));
+        let number = |value: &str| {
+            value
+                .trim()
+                .parse::<usize>()
+                .map_err(|_| format!("--spawn {kind} expects a number, got {value}"))
+        };
+        Ok(match kind {
+            "continuous" => SpawnStrategy::Continuous,
+            "initial" => SpawnStrategy::InitialSeeding {
+                ticks: number(argument)? as u64,
+            },
+            "floor" => SpawnStrategy::PopulationFloor(number(argument)?),
+            "mutants" => SpawnStrategy::TopGenomeMutants {
+                genomes: number(argument)?,
+            },
+            "regions" => SpawnStrategy::Regions(
+                argument
+                    .split(';')
+                    .map(|region| match region.split(',').collect::<Vec<_>>()[..] {
+                        [x, y, width, height] => Ok(SpawnRegion {
+                            x: number(x)?,
+                            y: number(y)?,
+                            width: number(width)?,
+                            height: number(height)?,
+                        }),
+                        _ => Err(format!(
+                            "--spawn regions expects X,Y,WIDTH,HEIGHT, got {region}"
+                        )),
+                    })
+                    .collect::<Result<_, _>>()?,
+            ),
+            _ => {
+                return Err(format!(
+                    "--spawn expects continuous, initial, floor, mutants or regions, got {strategy}"
+                ));
+            }
+        })
+    }
+
+    /// Parses the food options: the model is `uniform`, `logistic:RATE` or `spread`, sources are squares
+    /// around points and biome rates are relative factors.
+    fn food_growth(
+        model: Option<&String>,
+        sources: Option<&String>,
+        biome_rates: Option<&String>,
+    ) -> Result<FoodGrowth, String> {
+        let model = match model.map(String::as_str) {
+            None | Some("uniform") => GrowthModel::Uniform,
+            Some("spread") => GrowthModel::Spread,
+            Some(model) => match model.split_once(':') {
+                Some(("logistic", rate)) => {
+                    let [rate] = numbers("--food logistic", rate)?;
+                    GrowthModel::Logistic { rate }
+                }
+                _ => {
+                    return Err(format!(
+                        "--food expects uniform, logistic:RATE or spread, got {model}"
+                    ));
+                }
+            },
+        };
+        let sources = match sources {
+            Some(sources) => sources
+                .split(';')
+                .map(|source| {
+                    let [x, y, radius] = numbers("--food-sources", source)?;
+                    Ok(FoodSource { x, y, radius })
+                })
+                .collect::<Result<_, String>>()?,
+            None => Vec::new(),
+        };
+        let biome_rates = match biome_rates {
+            Some(rates) => {
+                let [meadow, forest, desert] = numbers("--biome-rates", rates)?;
+                BiomeRates {
+                    meadow,
+                    forest,
+                    desert,
+                }
+            }
+            None => BiomeRates::default(),
+        };
+
+        Ok(FoodGrowth {
+            model,
+            sources,
+            biome_rates,
+        })
+    }
+
+    /// Parses the seasons, `--food-season PERIOD,AMPLITUDE` being short for seasons that only vary food regrowth.
+    fn seasons(
+        seasons: Option<&String>,
+        food_season: Option<&String>,
+    ) -> Result<Option<Cycle>, String> {
+        match (seasons, food_season) {
+            (Some(_), Some(_)) => {
+                Err("--seasons and --food-season both set the seasons".to_string())
+            }
+            (Some(seasons), None) => cycle("--seasons", seasons).map(Some),
+            (None, Some(season)) => {
+                let [period, amplitude] = numbers::<f32, 2>("--food-season", season)?;
+                Ok(Some(Cycle {
+                    period: period as u64,
+                    food: amplitude,
+                    movement: 0.0,
+                    hazard: 0.0,
+                }))
+            }
+            (None, None) => Ok(None),
+        }
+    }
+
+    /// Parses `PERIOD,FOOD,MOVEMENT,HAZARD`, the period in ticks followed by the amplitudes.
+    fn cycle(name: &str, value: &str) -> Result<Cycle, String> {
+        let [period, food, movement, hazard] = numbers::<f32, 4>(name, value)?;
+        Ok(Cycle {
+            period: period as u64,
+            food,
+            movement,
+            hazard,
+        })
+    }
+
+    /// Parses `TICK:KIND:X,Y,RADIUS` entries separated by semicolons, the kind being `meteor`, `famine`,
+    /// `lava-flood` or `bloom`.
+    fn catastrophes(value: &str) -> Result<Vec<ScheduledCatastrophe>, String> {
+        value
+            .split(';')
+            .map(|entry| {
+                let [tick, kind, area] = entry.split(':').collect::<Vec<_>>()[..] else {
+                    return Err(format!(
+                        "--catastrophes expects TICK:KIND:X,Y,RADIUS, got {entry}"
+                    ));
+                };
+                let kind = match kind {
+                    "meteor" => CatastropheKind::Meteor,
+                    "famine" => CatastropheKind::Famine,
+                    "lava-flood" => CatastropheKind::LavaFlood,
+                    "bloom" => CatastropheKind::Bloom,
+                    _ => {
+                        return Err(format!(
+                            "--catastrophes expects meteor, famine, lava-flood or bloom, got {kind}"
+                        ));
+                    }
+                };
+                let [x, y, radius] = numbers("--catastrophes", area)?;
+                let [tick] = numbers("--catastrophes", tick)?;
+                Ok(ScheduledCatastrophe {
+                    tick,
+                    catastrophe: Catastrophe { kind, x, y, radius },
+                })
+            })
+            .collect()
+    }
+
+    /// Parses exactly `N` comma separated numbers.
+    fn numbers<T: std::str::FromStr, const N: usize>(
+        name: &str,
+        values: &str,
+    ) -> Result<[T; N], String> {
+        let numbers = values
+            .split(',')
+            .map(|value| {
+                value
+                    .trim()
+                    .parse()
+                    .map_err(|_| format!("{name} expects numbers, got {value}"))
+            })
+            .collect::<Result<Vec<T>, _>>()?;
+        numbers
+            .try_into()
+            .map_err(|_| format!("{name} expects {N} numbers separated by commas, got {values}"))
+    }
+
+    /// Records the ticks once, then replays them with every given number of threads.
+    ///
+    /// Replaying skips the decisions, so this measures applying them. Every run has to end up in the
+    /// recorded world, otherwise the result depends on the threads.
+    fn bench_threads(threads: &str, ticks: u64, settings: WorldSettings) -> Result<(), String> {
+        let counts = threads
+            .split(',')
+            .map(|count| {
+                count
+                    .trim()
+                    .parse()
+                    .map_err(|_| format!("--bench-threads expects numbers, got {count}"))
+            })
+            .collect::<Result<Vec<usize>, _>>()?;
+
+        let mut world = World::new(1000, 1000, Tile::Lava, settings);
+        let recording = Arc::new(Mutex::new(Some(Replay::new(&world))));
+        world.add_observer(ReplayRecorder::new(recording.clone()));
+        // Every thread count is checked against the world the serial order led to
+        world.set_serial(true);
+        for _ in 0..ticks {
+            world.tick();
+        }
+        let replay = recording
+            .lock()
+            .expect("Recording panicked")
+            .take()
+            .expect("Recording was started");
+        let expected = world.fingerprint();
+
+        for count in counts {
+            let pool = rayon::ThreadPoolBuilder::new()
+                .num_threads(count)
+                .build()
+                .map_err(|err| format!("Failed to start {count} threads: {err}"))?;
+            let mut player = ReplayPlayer::new(replay.clone());
+            let start = Instant::now();
+            pool.install(|| while player.step() {});
+            let elapsed = start.elapsed().as_secs_f64();
+            let outcome = if player.world().fingerprint() == expected {
+                "same world"
+            } else {
+                "DIFFERENT world"
+            };
+            println!(
+                "{count:>3} threads: {:>9.1} ticks/s, {outcome}",
+                ticks as f64 / elapsed
+            );
+        }
+        Ok(())
+    }
+
+    /// Evaluates the brains of a world evolved for `ticks` with the interpreter, the compiled networks and in
+    /// batches. All three have to come to the same decisions.
+    fn bench_brains(rounds: u32, ticks: u64, settings: WorldSettings) {
+        let mut world = World::new(1000, 1000, Tile::Lava, settings);
+        for _ in 0..ticks {
+            world.tick();
+        }
+
+        let comparison = compare_evaluators(&world, rounds);
+        let decisions = (comparison.decisions * rounds as usize) as f64;
+        for (name, elapsed) in [
+            ("interpreted", comparison.interpreted),
+            ("compiled", comparison.compiled),
+            ("batched", comparison.batched),
+        ] {
+            println!(
+                "{name:>11}: {:>12.0} decisions/s",
+                decisions / elapsed.as_secs_f64()
+            );
+        }
+        if comparison.mismatches == 0 {
+            println!("All {} decisions agree", comparison.decisions);
+        } else {
+            println!(
+                "{} of {} decisions DIFFER from the interpreter",
+                comparison.mismatches, comparison.decisions
+            );
+        }
+    }
+}
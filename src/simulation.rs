@@ -1,15 +1,51 @@
-use std::{collections::HashMap, fmt::Debug, ops::Add, sync::Arc};
+use std::{
+    collections::{BTreeMap, HashMap},
+    fmt::Debug,
+    hash::{DefaultHasher, Hash, Hasher},
+    ops::Add,
+    sync::Arc,
+};
 
 use iced::widget::image;
 use rayon::prelude::*;
-
-use neural_network::{Action, Location, NeuralNetwork, NeuralTick};
-
-use crate::ui::Board;
-
+use serde::{Deserialize, Serialize};
+
+use catastrophe::{Catastrophe, ScheduledCatastrophe};
+use clock::{Environment, WorldClock};
+use dirty::{Block, DirtyRegions};
+use food::FoodGrowth;
+use genome::{DEFAULT_ENERGY_CAPACITY, DEFAULT_VIEW_RANGE, Genome};
+use heatmap::HeatmapLayer;
+use lineage::Lineage;
+use neural_network::{Action, Location, NeuralNetwork};
+use observer::{Observers, WorldEvent, WorldObserver};
+use partition::{Effects, Partition};
+use pheromone::PheromoneField;
+use replay::TickRecord;
+use spawn::SpawnStrategy;
+use species::{Species, SpeciesCensus, SpeciesTracker};
+use statistics::Statistics;
+use tile_grid::{CHUNK_SIZE, TileGrid};
+
+pub mod catastrophe;
+pub mod clock;
+pub mod dirty;
+pub mod food;
+pub mod genome;
+pub mod heatmap;
+pub mod lineage;
 pub mod neural_network;
-
-#[derive(Hash, Debug, PartialEq, Eq, Clone)]
+pub mod observer;
+mod partition;
+pub mod pheromone;
+pub mod replay;
+pub mod rewind;
+pub mod spawn;
+pub mod species;
+pub mod statistics;
+pub mod tile_grid;
+
+#[derive(Hash, Debug, PartialEq, Eq, Clone, Serialize, Deserialize)]
 pub struct Position {
     pub x: usize,
     pub y: usize,
@@ -53,10 +89,18 @@ impl Position {
         }
     }
 
-    fn randomize(width: usize, height: usize) -> Self {
+    /// Shifts the position by a signed offset, returning `None` when it would leave the grid's origin.
+    fn offset(&self, dx: isize, dy: isize) -> Option<Self> {
+        Some(Self {
+            x: self.x.checked_add_signed(dx)?,
+            y: self.y.checked_add_signed(dy)?,
+        })
+    }
+
+    fn randomize(width: usize, height: usize, rng: &mut fastrand::Rng) -> Self {
         Self {
-            x: fastrand::usize(0..width),
-            y: fastrand::usize(0..height),
+            x: rng.usize(0..width),
+            y: rng.usize(0..height),
         }
     }
 }
@@ -72,35 +116,177 @@ impl Add for Position {
     }
 }
 
-#[derive(Clone)]
+/// Position and ID of a creature along with the action it decided on
+pub type Decision = (Position, u64, Action);
+
+/// A network and the creatures sharing it
+type BrainGroup<'a> = (&'a Arc<NeuralNetwork>, Vec<(&'a Position, &'a Creature)>);
+
+#[derive(Clone, Serialize, Deserialize)]
 pub struct World {
     width: usize,
     height: usize,
-    tiles: Vec<Tile>,
+    tiles: TileGrid,
     creatures: HashMap<Position, Creature>,
     current_tick: u64,
     settings: WorldSettings,
+    pheromones: PheromoneField,
+    next_creature_id: u64,
+    /// Creatures placed along with the random spawns of the next tick, not saved as a replay records them with
+    /// the tick they enter in
+    #[serde(skip)]
+    queued_spawns: Vec<(Position, Creature)>,
+    /// Catastrophes that strike at the end of the next tick, not saved for the same reason
+    #[serde(skip)]
+    queued_catastrophes: Vec<Catastrophe>,
+    lineage: Lineage,
+    species: SpeciesTracker,
+    statistics: Statistics,
+    /// Blocks whose tile colors changed since the UI last redrew them
+    #[serde(skip)]
+    dirty: DirtyRegions,
+    #[serde(skip)]
+    observers: Observers,
+    /// Applies the actions on the calling thread only, see `set_serial`
+    #[serde(skip)]
+    serial: bool,
 }
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct WorldSettings {
+    /// Tiles picked per tick to maybe grow food on, see `food_growth`
     pub food_regen_rate: u16,
+    pub food_growth: FoodGrowth,
+    /// Edge length in tiles of the patches biomes are laid out in, 0 for meadow everywhere.
+    /// Only used when the world is created.
+    pub biome_size: usize,
     pub creature_generation_rate: u16,
+    pub attack_energy_cost: u16,
+    pub attack_damage: u16,
+    /// Fraction of the energy drained by an attack that ends up with the attacker.
+    pub attack_efficiency: f32,
+    pub share_energy_amount: u16,
+    pub pheromone_emit_amount: f32,
+    /// Share of a tile's pheromone exchanged with its neighbours per tick, between 0 and 1.
+    pub pheromone_diffusion: f32,
+    /// Share of a tile's pheromone that evaporates per tick, between 0 and 1.
+    pub pheromone_decay: f32,
+    /// Genetic distance below which two brains count as the same species
+    pub species_threshold: f32,
+    /// Ticks between two species clustering passes
+    pub species_interval: u64,
+    /// Seeds all randomness of the world, from the decisions and spawns to everything that happens after them
+    pub seed: u64,
+    /// Genetic distance from which two creatures refuse to mate
+    pub mate_max_distance: f32,
+    /// Chance for each gene to mutate when it is passed on
+    pub mutation_rate: f32,
+    /// Upper limit for the energy capacity genes
+    pub max_energy: u16,
+    pub food_energy: u16,
+    /// Energy every creature with a brain burns per tick, regardless of its action
+    pub basal_energy_cost: f32,
+    /// Additional energy burned per tick for every neuron in the brain
+    pub neuron_energy_cost: f32,
+    /// Additional energy burned per tick for every connection in the brain
+    pub connection_energy_cost: f32,
+    /// Extra energy for stepping onto bare ground
+    pub ground_move_cost: u16,
+    /// Extra energy for stepping onto ground overgrown with food
+    pub vegetation_move_cost: u16,
+    /// Ticks after which a membrane that never received DNA falls apart
+    pub membrane_lifetime: u64,
+    /// Energy a membrane loses per tick
+    pub membrane_energy_leak: u16,
+    /// Which random creatures enter the world, and where
+    pub spawn_strategy: SpawnStrategy,
+    /// Seasons and days the food regrowth, movement costs and lava heat follow
+    pub clock: WorldClock,
+    /// Energy a creature burns per tick while standing next to lava
+    pub lava_heat: u16,
+    /// Catastrophes that strike at the given ticks, besides the ones triggered by hand
+    pub catastrophes: Vec<ScheduledCatastrophe>,
+}
+
+impl Default for WorldSettings {
+    fn default() -> Self {
+        Self {
+            creature_generation_rate: 3,
+            food_regen_rate: 30,
+            food_growth: FoodGrowth::default(),
+            biome_size: 0,
+            attack_energy_cost: 5,
+            attack_damage: 50,
+            attack_efficiency: 0.5,
+            share_energy_amount: 20,
+            pheromone_emit_amount: 1.0,
+            pheromone_diffusion: 0.2,
+            pheromone_decay: 0.02,
+            species_threshold: 0.3,
+            species_interval: 100,
+            seed: 0,
+            mate_max_distance: 0.5,
+            mutation_rate: 0.05,
+            max_energy: 2000,
+            food_energy: 50,
+            basal_energy_cost: 0.2,
+            neuron_energy_cost: 0.02,
+            connection_energy_cost: 0.01,
+            ground_move_cost: 0,
+            vegetation_move_cost: 1,
+            membrane_lifetime: 200,
+            membrane_energy_leak: 1,
+            spawn_strategy: SpawnStrategy::default(),
+            clock: WorldClock::default(),
+            lava_heat: 0,
+            catastrophes: Vec::new(),
+        }
+    }
+}
+
+#[derive(Hash, Debug, PartialEq, Eq, Clone, Copy, Serialize, Deserialize)]
+pub enum DeathCause {
+    OldAge,
+    Starvation,
+    Terrain,
+    Predation,
+    /// A membrane fell apart before receiving any DNA
+    Decay,
+    /// Struck by a meteor or buried by a lava flood
+    Catastrophe,
 }
 
 impl World {
     pub fn new(width: usize, height: usize, border: Tile, settings: WorldSettings) -> Self {
-        let mut tiles = vec![Tile::default(); width * height];
+        let mut tiles = TileGrid::new(width, height, Tile::default());
+
+        if settings.biome_size > 0 {
+            // Square patches of a random biome each, laid out the same way for the same seed
+            let mut rng = fastrand::Rng::with_seed(settings.seed);
+            let patches_per_row = width.div_ceil(settings.biome_size);
+            let patches: Vec<Biome> = (0..patches_per_row * height.div_ceil(settings.biome_size))
+                .map(|_| Biome::randomize(&mut rng))
+                .collect();
+            for y in 0..height {
+                for x in 0..width {
+                    let patch = y / settings.biome_size * patches_per_row + x / settings.biome_size;
+                    if let Tile::Ground(data) = tiles.get_mut(&Position { x, y }) {
+                        data.biome = patches[patch];
+                    }
+                }
+            }
+        }
 
         for x in 0..width {
-            tiles[x] = border.clone();
-            tiles[width * (height - 1) + x] = border.clone();
+            *tiles.get_mut(&Position { x, y: 0 }) = border.clone();
+            *tiles.get_mut(&Position { x, y: height - 1 }) = border.clone();
         }
 
         for y in 1..height - 1 {
-            tiles[y * width] = border.clone();
-            tiles[y * width + width - 1] = border.clone();
+            *tiles.get_mut(&Position { x: 0, y }) = border.clone();
+            *tiles.get_mut(&Position { x: width - 1, y }) = border.clone();
         }
+        tiles.settle();
 
         let creatures = HashMap::new();
         let current_tick = 0;
@@ -112,6 +298,16 @@ impl World {
             creatures,
             current_tick,
             settings,
+            pheromones: PheromoneField::new(width, height),
+            next_creature_id: 0,
+            queued_spawns: Vec::new(),
+            queued_catastrophes: Vec::new(),
+            lineage: Lineage::default(),
+            species: SpeciesTracker::default(),
+            statistics: Statistics::new(width, height),
+            dirty: DirtyRegions::default(),
+            observers: Observers::default(),
+            serial: false,
         }
     }
 
@@ -127,9 +323,108 @@ impl World {
         self.current_tick
     }
 
+    pub fn lineage(&self) -> &Lineage {
+        &self.lineage
+    }
+
+    pub fn species(&self) -> &SpeciesTracker {
+        &self.species
+    }
+
+    pub fn settings(&self) -> &WorldSettings {
+        &self.settings
+    }
+
+    /// Swaps the rules the world runs by, takes effect with the next tick.
+    pub fn set_settings(&mut self, settings: WorldSettings) {
+        self.settings = settings;
+    }
+
+    /// Returns the blocks changed since the last call and starts tracking afresh.
+    pub fn take_dirty(&mut self) -> DirtyRegions {
+        std::mem::replace(
+            &mut self.dirty,
+            DirtyRegions::clean(self.width, self.height),
+        )
+    }
+
+    /// Applies the actions one partition after the other on the calling thread instead of in parallel.
+    ///
+    /// Both take the same order and lead to the same world, the serial one is there to check the parallel one
+    /// against.
+    pub fn set_serial(&mut self, serial: bool) {
+        self.serial = serial;
+    }
+
+    /// Starts recording heatmaps over roughly the last `window` ticks, or stops with `None`.
+    pub fn set_heatmap_window(&mut self, window: Option<u64>) {
+        self.statistics.set_heatmap_window(window);
+    }
+
+    /// Per-tile intensities of the layer between 0 and 1, `None` while heatmaps are not recorded.
+    pub fn heatmap(&self, layer: HeatmapLayer) -> Option<Vec<f32>> {
+        self.statistics.heatmap(layer, self.current_tick)
+    }
+
+    /// Hash of the creatures, food and counters, to tell cheaply whether two runs ended up in the same world.
+    pub fn fingerprint(&self) -> u64 {
+        let mut hasher = DefaultHasher::new();
+        self.current_tick.hash(&mut hasher);
+        self.next_creature_id.hash(&mut hasher);
+        let mut creatures: Vec<_> = self.creatures.iter().collect();
+        creatures.sort_unstable_by_key(|(position, _)| (position.y, position.x));
+        for (position, creature) in creatures {
+            position.hash(&mut hasher);
+            creature.id.hash(&mut hasher);
+            creature.energy.hash(&mut hasher);
+            creature.offspring.hash(&mut hasher);
+            creature.genome_id().hash(&mut hasher);
+        }
+        for tile in self.tiles.iter() {
+            tile.food().to_bits().hash(&mut hasher);
+        }
+        for cause in [
+            DeathCause::OldAge,
+            DeathCause::Starvation,
+            DeathCause::Terrain,
+            DeathCause::Predation,
+            DeathCause::Decay,
+            DeathCause::Catastrophe,
+        ] {
+            self.statistics.death_counts().get(&cause).hash(&mut hasher);
+        }
+        hasher.finish()
+    }
+
+    /// Registers an observer that gets called for every event from now on.
+    ///
+    /// Observers are not cloned along with the world, so checkpoints and the worlds forked from them start without
+    /// any and need theirs registered again. The lineage and statistics are part of the world and do carry over.
+    pub fn add_observer(&mut self, observer: impl WorldObserver + 'static) {
+        self.observers.push(Box::new(observer));
+    }
+
+    /// Hands the event to the lineage and statistics first, then to the registered observers.
+    fn notify(&mut self, event: impl FnOnce() -> WorldEvent) {
+        let event = event();
+        self.lineage.on_event(self.current_tick, &event);
+        self.statistics.on_event(self.current_tick, &event);
+        self.observers.notify(self.current_tick, || event);
+    }
+
+    /// Whether anything besides the lineage and statistics listens to the events, like a replay recorder
+    fn observed(&self) -> bool {
+        !self.observers.is_empty()
+    }
+
+    /// Builds and hands out an event that only the registered observers care about, if there are any.
+    fn notify_observers(&mut self, event: impl FnOnce() -> WorldEvent) {
+        self.observers.notify(self.current_tick, event);
+    }
+
     fn get_tile(&self, position: &Position) -> Option<&Tile> {
         if self.check_bounds(position) {
-            Some(&self.tiles[position.y * self.width + position.x])
+            Some(self.tiles.get(position))
         } else {
             None
         }
@@ -142,17 +437,26 @@ impl World {
     pub fn tick(&mut self) {
         self.current_tick += 1;
         let actions = self
-            .creatures
+            .brain_groups()
             .par_iter()
-            .filter_map(|(position, creature)| {
-                if let Some(brain) = &creature.brain {
-                    let mut neural_tick = NeuralTick::seed(brain, creature, position, &self);
-                    let action = neural_tick.calculate_action(brain);
-
-                    Some((position.clone(), action))
-                } else {
-                    None
-                }
+            .flat_map(|(brain, members)| {
+                members
+                    .par_chunks(DECISION_BATCH_SIZE)
+                    .flat_map_iter(|batch| {
+                        let seed = partition::decision_seed(
+                            self.settings.seed,
+                            self.current_tick,
+                            batch[0].0,
+                        );
+                        let mut rng = fastrand::Rng::with_seed(seed);
+                        let actions = brain.compiled().decide_batch(batch, self, &mut rng);
+                        batch
+                            .iter()
+                            .zip(actions)
+                            .map(|((position, creature), action)| {
+                                ((*position).clone(), creature.id, action)
+                            })
+                    })
             })
             .collect_vec_list();
         // for (position, creature) in self.creatures.iter() {
@@ -165,147 +469,261 @@ impl World {
         //     }
         // }
 
-        for (position, action) in actions.into_iter().flatten() {
-            self.apply_action(&position, action);
+        let mut new_creatures = std::mem::take(&mut self.queued_spawns);
+        new_creatures.extend(self.settings.spawn_strategy.generate(self));
+        let mut catastrophes = std::mem::take(&mut self.queued_catastrophes);
+        catastrophes.extend(self.scheduled_catastrophes());
+
+        self.resolve_tick(actions.into_iter().flatten(), new_creatures, catastrophes);
+    }
+
+    /// Creatures with a brain, grouped by their network so each group can be decided on in one batch.
+    ///
+    /// Members are sorted by position, so the batches and the seeds they draw from don't depend on the order
+    /// of the map.
+    fn brain_groups(&self) -> Vec<BrainGroup<'_>> {
+        let mut groups =
+            neural_network::group_by_network(self.creatures.iter().filter_map(|member| {
+                let genome = member.1.genome.as_ref()?;
+                Some((&genome.brain, member))
+            }));
+        for (_, members) in &mut groups {
+            members.sort_unstable_by_key(|(position, _)| (position.y, position.x));
         }
+        groups
+    }
 
-        let mut new_creatures = Vec::with_capacity(self.settings.creature_generation_rate as usize);
-        (0..self.settings.creature_generation_rate)
-            .into_par_iter()
-            .map(|_| {
-                let position = Position::randomize(self.width, self.height);
-                let rotation = CardinalDirection::randomize();
-                let brain = Some(Arc::new(NeuralNetwork::randomize()));
-                (position, Creature::new(self.current_tick, rotation, brain))
-            })
-            .collect_into_vec(&mut new_creatures);
+    /// Repeats a recorded tick, taking the decisions, random spawns and catastrophes from the record instead of
+    /// computing them.
+    pub fn replay_tick(&mut self, record: &TickRecord) {
+        self.current_tick += 1;
+        // The record already holds whatever was queued
+        self.queued_spawns.clear();
+        self.queued_catastrophes.clear();
+        self.resolve_tick(
+            record.actions.iter().cloned(),
+            record.spawns.clone(),
+            record.catastrophes.clone(),
+        );
+    }
 
+    /// Applies the decisions, spawns and catastrophes of a tick and advances the environment.
+    ///
+    /// Actions are applied in parallel, see `Partition`, but in an order given by their positions and with
+    /// randomness seeded from them. Everything else runs on the calling thread with a generator seeded per tick,
+    /// so the same decisions and spawns always lead to the same world, no matter how many threads apply them.
+    fn resolve_tick(
+        &mut self,
+        actions: impl IntoIterator<Item = Decision>,
+        new_creatures: Vec<(Position, Creature)>,
+        catastrophes: Vec<Catastrophe>,
+    ) {
+        self.apply_actions(actions);
+
+        let mut partition = Partition::new(
+            &self.settings,
+            self.current_tick,
+            &self.tiles,
+            (self.width, self.height),
+            std::mem::take(&mut self.creatures),
+            self.next_creature_id,
+            self.observed(),
+        );
+        partition.decay_membranes();
         for (position, creature) in new_creatures {
-            self.spawn_creature(position, creature);
+            partition.spawn_random(position, creature);
         }
-
-        for _ in 0..self.settings.food_regen_rate {
-            self.regenerate_food();
+        let effects = partition.finish();
+        self.next_creature_id = effects.next_id;
+        self.commit(effects);
+        for catastrophe in &catastrophes {
+            self.strike(catastrophe);
         }
-    }
 
-    fn apply_action(&mut self, position: &Position, action: Action) {
-        {
-            let creature = self
-                .creatures
-                .get_mut(position)
-                .expect("An action needs to execute on a position with a creature");
+        let mut rng =
+            fastrand::Rng::with_seed(partition::tick_seed(self.settings.seed, self.current_tick));
+        self.grow_food(&mut rng);
 
-            if self.current_tick - creature.born > MAX_CREATURE_LIFETIME {
-                self.kill_creature(position);
-                return;
-            }
+        self.pheromones.step(
+            self.settings.pheromone_diffusion,
+            self.settings.pheromone_decay,
+        );
 
-            let energy_cost = action.energy_cost();
-            if creature.energy >= energy_cost {
-                creature.energy = creature.energy.saturating_sub(action.energy_cost());
-            } else {
-                self.kill_creature(position);
-                return;
-            }
+        if self.current_tick.is_multiple_of(LINEAGE_PRUNE_INTERVAL) {
+            let living = self.creatures.values().map(|creature| creature.id);
+            self.lineage.prune(living);
+        }
 
-            match action {
-                Action::Idle => (),
-                Action::Eat => {
-                    let tile = &mut self.tiles[position.x + position.y * self.width];
-                    if let Tile::Ground(data) = tile {
-                        if data.food_1 {
-                            data.food_1 = false;
-                            creature.energy += 50;
-                        }
-                    }
-                }
-                Action::Move(location) => {
-                    let new_position = creature.relative_position(position, location);
-                    if self.check_bounds(&new_position) {
-                        self.move_creature(position, new_position);
-                    }
-                }
-                Action::Rotate(rotation) => {
-                    creature.rotation.rotate(rotation);
-                }
-                Action::CreateMembrane(location) => {
-                    let spawn_position = creature.relative_position(position, location);
-                    let rotation = location.to_cardinal(creature.rotation);
-                    creature.offspring += 1;
-                    let new_creature = Creature::new(self.current_tick, rotation, None);
-                    self.spawn_creature(spawn_position, new_creature);
-                }
-                Action::CopyDna(location) => {
-                    let copy_position = creature.relative_position(position, location);
-                    self.copy_dna(position, &copy_position);
+        if self
+            .current_tick
+            .is_multiple_of(self.settings.species_interval)
+        {
+            self.update_species();
+        }
+
+        if self.statistics.heatmap_window().is_some() {
+            for (position, creature) in &self.creatures {
+                if !creature.is_membrane() {
+                    self.statistics
+                        .record_presence(position, creature.energy, self.current_tick);
                 }
             }
         }
+
+        self.notify_observers(|| WorldEvent::TickEnded);
     }
 
-    fn move_creature(&mut self, old_position: &Position, new_position: Position) {
-        if self.creatures.contains_key(&new_position) {
-            return;
+    /// Applies the actions phase by phase, in parallel across the chunks of a phase unless the world is serial.
+    ///
+    /// Each chunk's actions run in the order of their positions. IDs for the creatures that may be born are
+    /// reserved up front in the same order, so the IDs don't depend on which partition finishes first.
+    fn apply_actions(&mut self, actions: impl IntoIterator<Item = Decision>) {
+        let chunks_per_row = self.width.div_ceil(CHUNK_SIZE);
+        let chunk_rows = self.height.div_ceil(CHUNK_SIZE);
+        let mut chunks: BTreeMap<(usize, usize), Vec<Decision>> = BTreeMap::new();
+        for (position, id, action) in actions {
+            let chunk = partition::chunk_of(chunks_per_row, &position);
+            chunks
+                .entry((partition::phase(chunks_per_row, chunk), chunk))
+                .or_default()
+                .push((position, id, action));
         }
-        if let Some(creature) = self.creatures.remove(&old_position) {
-            let tile = self
-                .get_tile(&new_position)
-                .expect("Coordinate should be correct");
-            if !tile.can_contain_creature() {
-                // println!("Creature died to terrain");
-                return;
-            }
-            self.creatures.insert(new_position, creature);
+
+        let mut phases: [Vec<_>; partition::PHASES] = Default::default();
+        for ((phase, chunk), mut actions) in chunks {
+            actions.sort_unstable_by_key(|(position, _, _)| (position.y, position.x));
+            let births = actions
+                .iter()
+                .filter(|(_, _, action)| partition::may_give_birth(action))
+                .count();
+            phases[phase].push((chunk, self.next_creature_id, actions));
+            self.next_creature_id += births as u64;
         }
-    }
 
-    fn spawn_creature(&mut self, position: Position, creature: Creature) {
-        let tile = self
-            .get_tile(&position)
-            .expect("Coordinate should be correct");
-        if !tile.can_contain_creature() {
-            return;
+        for jobs in phases {
+            let owners: HashMap<usize, usize> = jobs
+                .iter()
+                .enumerate()
+                .map(|(index, (chunk, _, _))| (*chunk, index))
+                .collect();
+            let owner = |position: &Position| {
+                partition::reaching_chunks(chunks_per_row, chunk_rows, position)
+                    .find_map(|chunk| owners.get(&chunk).copied())
+            };
+            let mut creatures: Vec<HashMap<Position, Creature>> =
+                jobs.iter().map(|_| HashMap::new()).collect();
+            for (position, creature) in self
+                .creatures
+                .extract_if(|position, _| owner(position).is_some())
+            {
+                let index = owner(&position).expect("Creature was extracted for its owner");
+                creatures[index].insert(position, creature);
+            }
+
+            let observed = self.observed();
+            let run = |((_, next_id, actions), creatures)| {
+                let mut partition = Partition::new(
+                    &self.settings,
+                    self.current_tick,
+                    &self.tiles,
+                    (self.width, self.height),
+                    creatures,
+                    next_id,
+                    observed,
+                );
+                partition.apply_actions(actions);
+                partition.finish()
+            };
+            let effects: Vec<Effects> = if self.serial {
+                jobs.into_iter().zip(creatures).map(run).collect()
+            } else {
+                jobs.into_par_iter().zip(creatures).map(run).collect()
+            };
+            for effects in effects {
+                self.commit(effects);
+            }
         }
-        self.creatures.entry(position).or_insert(creature);
     }
 
-    fn copy_dna(&mut self, old_position: &Position, new_position: &Position) {
-        let [source, destination] = self
-            .creatures
-            .get_disjoint_mut([old_position, new_position]);
-        if let Some((source, destination)) = source.zip(destination) {
-            destination.brain = source.brain.clone();
+    /// Writes back what a partition did, in the order it happened.
+    fn commit(&mut self, effects: Effects) {
+        self.creatures.extend(effects.creatures);
+        for position in effects.eaten {
+            if let Tile::Ground(data) = self.tiles.get_mut(&position) {
+                data.food = 0.0;
+            }
+            self.dirty.mark(&position);
+        }
+        for (kind, position) in effects.emissions {
+            self.pheromones
+                .emit(kind, &position, self.settings.pheromone_emit_amount);
+        }
+        for event in effects.events {
+            self.notify(|| event);
         }
     }
 
-    fn kill_creature(&mut self, position: &Position) {
-        self.creatures.remove(position);
+    fn update_species(&mut self) {
+        let brains = self.creatures.iter().filter_map(|(position, creature)| {
+            let genome = creature.genome.as_ref()?;
+            Some((&genome.brain, position.clone()))
+        });
+        let assignment =
+            self.species
+                .cluster(self.current_tick, self.settings.species_threshold, brains);
+
+        for creature in self.creatures.values_mut() {
+            creature.species = None;
+        }
+        for (position, species) in assignment {
+            if let Some(creature) = self.creatures.get_mut(&position) {
+                creature.species = Some(species);
+            }
+        }
     }
 
-    fn regenerate_food(&mut self) {
-        let position = Position::randomize(self.width, self.height);
-        let index = position.x + position.y * self.width;
-        match &mut self.tiles[index] {
-            Tile::Ground(data) => {
-                data.food_1 = true;
-            }
-            Tile::Lava => {}
+    /// Color seen by eyes: a creature's body color, or the tile's color where there is none.
+    fn visible_color(&self, position: &Position) -> Option<Color> {
+        match self
+            .creatures
+            .get(position)
+            .and_then(|creature| creature.genome.as_ref())
+        {
+            Some(genome) => Some(genome.color.clone()),
+            None => self.get_tile(position).map(Tile::color),
         }
     }
 
+    /// Cheap enough to take every frame: tiles and pheromones are shared with the world until it
+    /// writes to them, and creatures are reduced to what it takes to draw them.
     pub fn snapshot(&self) -> Snapshot {
         Snapshot {
             current_tick: self.current_tick,
             width: self.width,
             height: self.height,
             tiles: self.tiles.clone(),
-            creatures: self.creatures.clone(),
+            creatures: self
+                .creatures
+                .iter()
+                .map(|(position, creature)| (position.clone(), creature.look()))
+                .collect(),
+            membrane_count: self
+                .creatures
+                .values()
+                .filter(|creature| creature.is_membrane())
+                .count(),
+            death_counts: self.statistics.death_counts().clone(),
+            pheromones: self.pheromones.clone(),
+            most_successful_genome: self.lineage.most_successful_genome(),
+            top_species: self.species.top(TOP_SPECIES_COUNT),
+            species_census: self.species.history().last().cloned(),
+            heatmap: None,
+            environment: self.settings.clock.environment(self.current_tick),
         }
     }
 }
 
-#[derive(Clone)]
+#[derive(Clone, Serialize, Deserialize)]
 pub enum Tile {
     Ground(AccessableTileData),
     Lava,
@@ -323,6 +741,32 @@ impl Tile {
         }
     }
 
+    /// Whether food can grow on the tile
+    fn can_grow(&self) -> bool {
+        matches!(self, Tile::Ground(data) if data.food < 1.0)
+    }
+
+    /// Amount of food on the tile, between 0 and 1
+    pub fn food(&self) -> f32 {
+        match self {
+            Tile::Ground(data) => data.food,
+            Tile::Lava => 0.0,
+        }
+    }
+
+    /// Energy needed on top of the move action to step onto this tile, the more vegetation the closer it gets to
+    /// `vegetation_move_cost`
+    fn movement_cost(&self, settings: &WorldSettings) -> u16 {
+        match self {
+            Tile::Ground(data) => {
+                let ground = settings.ground_move_cost as f32;
+                let vegetation = settings.vegetation_move_cost as f32;
+                (ground + (vegetation - ground) * data.food).round() as u16
+            }
+            Tile::Lava => 0,
+        }
+    }
+
     pub fn color(&self) -> Color {
         match self {
             Tile::Ground(data) => data.color(),
@@ -335,44 +779,103 @@ impl Tile {
     }
 }
 
-#[derive(Clone)]
+#[derive(Clone, Serialize, Deserialize)]
 pub struct AccessableTileData {
-    food_1: bool,
+    /// Between 0 for bare ground and 1 for fully grown food
+    food: f32,
+    biome: Biome,
 }
 
 impl AccessableTileData {
     fn default() -> Self {
-        AccessableTileData { food_1: true }
+        AccessableTileData {
+            food: 1.0,
+            biome: Biome::Meadow,
+        }
     }
 
+    /// Color of the bare biome, shading into the color of its vegetation with the amount of food
     fn color(&self) -> Color {
-        if self.food_1 {
-            Color {
-                r: 96,
-                g: 192,
-                b: 0,
-            }
-        } else {
-            Color { r: 0, g: 0, b: 0 }
+        let (bare, grown) = match self.biome {
+            Biome::Meadow => (
+                Color { r: 0, g: 0, b: 0 },
+                Color {
+                    r: 96,
+                    g: 192,
+                    b: 0,
+                },
+            ),
+            Biome::Forest => (
+                Color { r: 0, g: 32, b: 16 },
+                Color {
+                    r: 32,
+                    g: 128,
+                    b: 32,
+                },
+            ),
+            Biome::Desert => (
+                Color {
+                    r: 64,
+                    g: 48,
+                    b: 16,
+                },
+                Color {
+                    r: 176,
+                    g: 192,
+                    b: 64,
+                },
+            ),
+        };
+        bare.blend(&grown, self.food)
+    }
+}
+
+/// Kind of land a ground tile belongs to, food grows back at a different rate in each
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum Biome {
+    Meadow,
+    Forest,
+    Desert,
+}
+
+impl Biome {
+    fn randomize(rng: &mut fastrand::Rng) -> Self {
+        match rng.u8(0..3) {
+            0 => Biome::Meadow,
+            1 => Biome::Forest,
+            _ => Biome::Desert,
         }
     }
 }
 
-#[derive(PartialEq)]
+#[derive(Clone, PartialEq, Hash, Debug, Serialize, Deserialize)]
 pub struct Color {
     r: u8,
     g: u8,
     b: u8,
 }
 impl Color {
-    fn randomize() -> Color {
+    fn randomize(rng: &mut fastrand::Rng) -> Color {
         Color {
-            r: fastrand::u8(0..255),
-            g: fastrand::u8(0..255),
-            b: fastrand::u8(0..255),
+            r: rng.u8(0..255),
+            g: rng.u8(0..255),
+            b: rng.u8(0..255),
         }
     }
 
+    /// Shifts one random channel by up to 16 in either direction.
+    fn mutate(&self, rng: &mut fastrand::Rng) -> Color {
+        let offset = rng.i8(-16..=16);
+        let shift = |channel: u8| channel.saturating_add_signed(offset);
+        let mut color = self.clone();
+        match rng.u8(0..3) {
+            0 => color.r = shift(color.r),
+            1 => color.g = shift(color.g),
+            _ => color.b = shift(color.b),
+        }
+        color
+    }
+
     pub fn to_iced(&self) -> iced::Color {
         iced::Color::from_rgb8(self.r, self.g, self.b)
     }
@@ -380,8 +883,51 @@ impl Color {
     pub fn to_u8(&self) -> [u8; 4] {
         [self.r, self.g, self.b, 255]
     }
+
+    /// Linear interpolation towards `other`, where a `factor` of 1.0 yields `other`.
+    fn blend(&self, other: &Color, factor: f32) -> Color {
+        let mix = |a: u8, b: u8| (a as f32 + (b as f32 - a as f32) * factor) as u8;
+        Color {
+            r: mix(self.r, other.r),
+            g: mix(self.g, other.g),
+            b: mix(self.b, other.b),
+        }
+    }
 }
 
+/// Spreads species IDs over the hue circle so neighbouring IDs get distinct colors
+fn species_color(species: u64) -> Color {
+    let hue = (species as f32 * 0.618_034).fract() * 6.0;
+    let x = (255.0 * (1.0 - (hue % 2.0 - 1.0).abs())).round() as u8;
+    let (r, g, b) = match hue as u8 {
+        0 => (255, x, 0),
+        1 => (x, 255, 0),
+        2 => (0, 255, x),
+        3 => (0, x, 255),
+        4 => (x, 0, 255),
+        _ => (255, 0, x),
+    };
+    Color { r, g, b }
+}
+
+const MEMBRANE_COLOR: Color = Color {
+    r: 153,
+    g: 153,
+    b: 153,
+};
+
+const PHEROMONE_OVERLAY_COLOR: Color = Color {
+    r: 255,
+    g: 0,
+    b: 255,
+};
+
+const HEATMAP_COLOR: Color = Color {
+    r: 255,
+    g: 140,
+    b: 0,
+};
+
 impl PartialOrd for Color {
     fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
         if self.r > other.r && self.g > other.g && self.b > other.b {
@@ -392,36 +938,165 @@ impl PartialOrd for Color {
     }
 }
 
-#[derive(Clone)]
+#[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct Creature {
+    id: u64,
     born: u64,
     energy: u16,
     rotation: CardinalDirection,
-    brain: Option<Arc<NeuralNetwork>>,
+    genome: Option<Genome>,
     offspring: u64,
+    /// Fraction of basal energy cost that has not been paid yet, since energy is only paid in whole units
+    metabolic_debt: f32,
+    /// Signal channel this creature currently shows to its neighbours
+    signal: Option<u8>,
+    /// Assigned by the periodic species clustering, inherited on DNA copies in between
+    species: Option<u64>,
 }
 
 const INITIAL_CREATURE_ENERGY: u16 = 100;
 const MAX_CREATURE_LIFETIME: u64 = 10000;
+/// Offspring share of creatures without a genome
+const DEFAULT_OFFSPRING_SHARE: f32 = 0.5;
+const LINEAGE_PRUNE_INTERVAL: u64 = 1000;
+const TOP_SPECIES_COUNT: usize = 5;
+/// Most creatures sharing a network that are decided on together, larger groups are split across threads
+const DECISION_BATCH_SIZE: usize = 256;
 
 impl Creature {
-    fn new(born: u64, rotation: CardinalDirection, brain: Option<Arc<NeuralNetwork>>) -> Self {
+    fn new(born: u64, rotation: CardinalDirection, genome: Option<Genome>) -> Self {
         Creature {
+            // Assigned once the creature is actually placed in the world
+            id: 0,
             born,
             energy: INITIAL_CREATURE_ENERGY,
             rotation,
-            brain,
+            genome,
             offspring: 0,
+            metabolic_debt: 0.0,
+            signal: None,
+            species: None,
+        }
+    }
+
+    pub fn id(&self) -> u64 {
+        self.id
+    }
+
+    /// A membrane is an empty body waiting for DNA
+    pub fn is_membrane(&self) -> bool {
+        self.genome.is_none()
+    }
+
+    /// Drawn in the color of its species, membranes as translucent gray
+    fn look(&self) -> CreatureLook {
+        if self.is_membrane() {
+            return CreatureLook {
+                color: MEMBRANE_COLOR,
+                opacity: 0.5,
+            };
         }
+        let color = match self.species {
+            Some(species) => species_color(species),
+            None => Color {
+                r: 255,
+                g: 255,
+                b: 255,
+            },
+        };
+        CreatureLook {
+            color,
+            opacity: 1.0,
+        }
+    }
+
+    pub fn species(&self) -> Option<u64> {
+        self.species
+    }
+
+    fn genome_id(&self) -> Option<u64> {
+        self.genome.as_ref().map(|genome| genome.brain.genome_id())
+    }
+
+    fn max_lifetime(&self) -> u64 {
+        self.genome
+            .as_ref()
+            .map_or(MAX_CREATURE_LIFETIME, |genome| genome.max_lifetime)
+    }
+
+    fn energy_capacity(&self, settings: &WorldSettings) -> u16 {
+        self.genome
+            .as_ref()
+            .map_or(DEFAULT_ENERGY_CAPACITY, |genome| genome.energy_capacity)
+            .min(settings.max_energy)
+    }
+
+    /// Energy handed to a single offspring
+    fn offspring_energy(&self) -> u16 {
+        let share = self
+            .genome
+            .as_ref()
+            .map_or(DEFAULT_OFFSPRING_SHARE, |genome| genome.offspring_share);
+        (self.energy as f32 * share) as u16
+    }
+
+    /// Tiles the creature sees along its rays and cones
+    fn view_range(&self) -> u8 {
+        self.genome
+            .as_ref()
+            .map_or(DEFAULT_VIEW_RANGE, |genome| genome.view_range)
+    }
+
+    fn metabolism(&self) -> f32 {
+        self.genome.as_ref().map_or(1.0, |genome| genome.metabolism)
+    }
+
+    /// Cost of an action, scaled by the creature's metabolism
+    fn energy_cost(&self, action: &Action, settings: &WorldSettings) -> u16 {
+        (action.energy_cost(settings) as f32 * self.metabolism()).round() as u16
+    }
+
+    /// Accumulates this tick's basal cost, which grows with the brain size, and returns the whole units that are due.
+    fn metabolize(&mut self, settings: &WorldSettings) -> u16 {
+        let Some(genome) = &self.genome else {
+            return 0;
+        };
+        let brain_cost = genome.brain.neuron_count() as f32 * settings.neuron_energy_cost
+            + genome.brain.connection_count() as f32 * settings.connection_energy_cost;
+        self.metabolic_debt += (settings.basal_energy_cost + brain_cost) * self.metabolism();
+
+        let due = self.metabolic_debt.floor();
+        self.metabolic_debt -= due;
+        due as u16
+    }
+
+    /// Adds energy up to the creature's capacity.
+    fn gain_energy(&mut self, amount: u16, settings: &WorldSettings) {
+        self.energy = self
+            .energy
+            .saturating_add(amount)
+            .min(self.energy_capacity(settings));
     }
 
     fn relative_position(&self, position: &Position, location: Location) -> Position {
         let cardinal = location.to_cardinal(self.rotation);
         position.cardinal(cardinal, 1)
     }
+
+    /// Like `relative_position`, but `None` refers to the creature's own tile.
+    fn optional_relative_position(
+        &self,
+        position: &Position,
+        location: Option<Location>,
+    ) -> Position {
+        match location {
+            Some(location) => self.relative_position(position, location),
+            None => position.clone(),
+        }
+    }
 }
 
-#[derive(Clone, Copy)]
+#[derive(Clone, Copy, Debug, Serialize, Deserialize)]
 pub enum CardinalDirection {
     North,
     East,
@@ -451,8 +1126,18 @@ impl CardinalDirection {
         }
     }
 
-    fn randomize() -> CardinalDirection {
-        match fastrand::u8(0..4) {
+    /// Unit step in grid coordinates, with north pointing towards smaller `y`.
+    fn delta(&self) -> (isize, isize) {
+        match self {
+            CardinalDirection::North => (0, -1),
+            CardinalDirection::East => (1, 0),
+            CardinalDirection::South => (0, 1),
+            CardinalDirection::West => (-1, 0),
+        }
+    }
+
+    fn randomize(rng: &mut fastrand::Rng) -> CardinalDirection {
+        match rng.u8(0..4) {
             0 => CardinalDirection::North,
             1 => CardinalDirection::East,
             2 => CardinalDirection::South,
@@ -466,8 +1151,54 @@ pub struct Snapshot {
     current_tick: u64,
     width: usize,
     height: usize,
-    tiles: Vec<Tile>,
-    creatures: HashMap<Position, Creature>,
+    tiles: TileGrid,
+    /// Position and look of every creature, membranes included
+    creatures: Vec<(Position, CreatureLook)>,
+    membrane_count: usize,
+    death_counts: HashMap<DeathCause, u64>,
+    pheromones: PheromoneField,
+    most_successful_genome: Option<(u64, u64)>,
+    top_species: Vec<Species>,
+    species_census: Option<SpeciesCensus>,
+    /// Intensities blended over the background, see `World::heatmap`
+    heatmap: Option<Vec<f32>>,
+    environment: Environment,
+}
+
+/// How a creature is drawn on the board and in exported frames.
+#[derive(Clone, Debug)]
+pub struct CreatureLook {
+    pub color: Color,
+    /// Share of the color covering the background, 1.0 hides it
+    pub opacity: f32,
+}
+
+/// The background as one image per block of tiles, kept to re-upload only the blocks that changed.
+#[derive(Default)]
+pub struct BackgroundCache {
+    /// Indexed like the blocks of `DirtyRegions`
+    blocks: Vec<BackgroundBlock>,
+    size: (usize, usize),
+}
+
+pub struct BackgroundBlock {
+    pub block: Block,
+    pub handle: image::Handle,
+    /// Whether the pheromone overlay changed any of the tile colors
+    scented: bool,
+    /// Heatmap intensities the block was painted with, see `Snapshot::heat`
+    heat: Vec<u8>,
+}
+
+impl BackgroundCache {
+    /// Forces a full repaint, for when the next snapshot comes from a different world.
+    pub fn invalidate(&mut self) {
+        self.blocks.clear();
+    }
+
+    pub fn blocks(&self) -> &[BackgroundBlock] {
+        &self.blocks
+    }
 }
 
 impl Debug for Snapshot {
@@ -476,8 +1207,9 @@ impl Debug for Snapshot {
             .field("current_tick", &self.current_tick)
             .field("width", &self.width)
             .field("height", &self.height)
-            .field("tiles", &self.tiles.len())
+            .field("tiles", &(self.width * self.height))
             .field("creatures", &self.creature_count())
+            .field("membranes", &self.membrane_count)
             .finish()
     }
 }
@@ -487,30 +1219,260 @@ impl Snapshot {
         self.current_tick
     }
 
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    /// Position and look of every creature, membranes included
+    pub fn creatures(&self) -> &[(Position, CreatureLook)] {
+        &self.creatures
+    }
+
+    /// Number of creatures with a brain, membranes are counted separately
     pub fn creature_count(&self) -> usize {
-        self.creatures.len()
+        self.creatures.len() - self.membrane_count
     }
 
+    pub fn membrane_count(&self) -> usize {
+        self.membrane_count
+    }
+
+    /// How the clock scaled the environment during the tick
+    pub fn environment(&self) -> Environment {
+        self.environment
+    }
+
+    /// Genome ID and descendant count of the genome that was passed on most often so far
+    pub fn most_successful_genome(&self) -> Option<(u64, u64)> {
+        self.most_successful_genome
+    }
+
+    pub fn top_species(&self) -> &[Species] {
+        &self.top_species
+    }
+
+    pub fn species_census(&self) -> Option<&SpeciesCensus> {
+        self.species_census.as_ref()
+    }
+
+    pub fn deaths(&self, cause: DeathCause) -> u64 {
+        self.death_counts.get(&cause).copied().unwrap_or(0)
+    }
+
+    /// Chunks holding any pheromone of the given kind, the rest of the world is skipped when it diffuses
+    pub fn active_pheromone_chunks(&self, kind: u8) -> usize {
+        self.pheromones.active_chunks(kind)
+    }
+
+    pub fn set_heatmap(&mut self, heatmap: Option<Vec<f32>>) {
+        self.heatmap = heatmap;
+    }
+
+    /// Renders the tiles into the cached block images, optionally tinting them by the concentration of one
+    /// pheromone kind and by the heatmap set on the snapshot, and uploads the ones that were repainted.
+    ///
+    /// Only blocks that are `dirty`, scented by the pheromone overlay now or in the cached image, or whose
+    /// heatmap intensities changed are repainted. The quiet parts of the world keep their images even while
+    /// an overlay is shown.
     pub fn background_upload(
         &self,
-    ) -> iced::Task<Result<iced::widget::image::Allocation, iced::widget::image::Error>> {
-        let image_data: Vec<u8> = self
-            .tiles
-            .iter()
-            .flat_map(|tile| tile.color().to_u8())
+        cache: &mut BackgroundCache,
+        dirty: &DirtyRegions,
+        pheromone_overlay: Option<u8>,
+    ) -> iced::Task<Vec<Result<image::Allocation, image::Error>>> {
+        let size = (self.width, self.height);
+        if cache.size != size || dirty.is_all() {
+            cache.blocks.clear();
+            cache.size = size;
+        }
+
+        let mut uploads = Vec::new();
+        for block in Block::all(self.width, self.height) {
+            let scented = self.is_scented(&block, pheromone_overlay);
+            let heat = self.heat(&block);
+            if let Some(cached) = cache.blocks.get(block.index)
+                && !scented
+                && !cached.scented
+                && heat == cached.heat
+                && !dirty.contains(&block)
+            {
+                continue;
+            }
+
+            let pixels: Vec<u8> = block
+                .rows
+                .clone()
+                .flat_map(|y| block.columns.clone().map(move |x| Position { x, y }))
+                .flat_map(|position| self.background_color(&position, pheromone_overlay).to_u8())
+                .collect();
+            let handle = image::Handle::from_rgba(
+                block.columns.len() as u32,
+                block.rows.len() as u32,
+                pixels,
+            );
+            uploads.push(image::allocate(handle.clone()));
+            let index = block.index;
+            let block = BackgroundBlock {
+                block,
+                handle,
+                scented,
+                heat,
+            };
+            // Blocks are visited in order, so a cleared cache fills up from the start
+            match cache.blocks.get_mut(index) {
+                Some(cached) => *cached = block,
+                None => cache.blocks.push(block),
+            }
+        }
+
+        if uploads.is_empty() {
+            return iced::Task::done(Vec::new());
+        }
+        iced::Task::batch(uploads).collect()
+    }
+
+    /// Whether the pheromone overlay changes the color of any tile of the block
+    fn is_scented(&self, block: &Block, pheromone_overlay: Option<u8>) -> bool {
+        let Some(kind) = pheromone_overlay else {
+            return false;
+        };
+        block.rows.clone().any(|y| {
+            block
+                .columns
+                .clone()
+                .any(|x| self.pheromones.get(kind, &Position { x, y }) > 0.0)
+        })
+    }
+
+    /// Heatmap intensities of the block's tiles at the precision of a color channel, empty when none of
+    /// them is tinted. Intensities fade a little every tick, so comparing them unrounded would repaint
+    /// every heated block all the time.
+    fn heat(&self, block: &Block) -> Vec<u8> {
+        let Some(heatmap) = &self.heatmap else {
+            return Vec::new();
+        };
+        let heat: Vec<u8> = block
+            .rows
+            .clone()
+            .flat_map(|y| block.columns.clone().map(move |x| y * self.width + x))
+            .map(|index| (heatmap[index].clamp(0.0, 1.0) * 255.0).round() as u8)
             .collect();
+        if heat.iter().all(|&value| value == 0) {
+            return Vec::new();
+        }
+        heat
+    }
 
-        let handle = image::Handle::from_rgba(self.width as u32, self.height as u32, image_data);
-        let task = iced::widget::image::allocate(handle);
-        task
+    /// RGBA pixels of the whole board with one pixel per tile, creatures drawn on top of the background.
+    pub fn render(&self, pheromone_overlay: Option<u8>) -> Vec<u8> {
+        let mut pixels = self.background_pixels(pheromone_overlay);
+        for (position, look) in &self.creatures {
+            let index = (position.y * self.width + position.x) * 4;
+            let background = Color {
+                r: pixels[index],
+                g: pixels[index + 1],
+                b: pixels[index + 2],
+            };
+            let color = background.blend(&look.color, look.opacity);
+            pixels[index..index + 4].copy_from_slice(&color.to_u8());
+        }
+        pixels
+    }
+
+    fn background_pixels(&self, pheromone_overlay: Option<u8>) -> Vec<u8> {
+        (0..self.height)
+            .flat_map(|y| (0..self.width).map(move |x| Position { x, y }))
+            .flat_map(|position| self.background_color(&position, pheromone_overlay).to_u8())
+            .collect()
+    }
+
+    fn background_color(&self, position: &Position, pheromone_overlay: Option<u8>) -> Color {
+        let mut color = self.tiles.get(position).color();
+        if let Some(kind) = pheromone_overlay {
+            let intensity = self.pheromones.get(kind, position).min(1.0);
+            color = color.blend(&PHEROMONE_OVERLAY_COLOR, intensity);
+        }
+        if let Some(heatmap) = &self.heatmap {
+            color = color.blend(
+                &HEATMAP_COLOR,
+                heatmap[position.y * self.width + position.x],
+            );
+        }
+        color
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+
+    use super::*;
+    use replay::{Replay, ReplayPlayer, ReplayRecorder};
+
+    /// Runs `ticks` serially and returns the recording of them along with the world they led to.
+    fn record(settings: WorldSettings, ticks: u64) -> (Replay, World) {
+        let mut world = World::new(192, 192, Tile::Lava, settings);
+        let recording = Arc::new(Mutex::new(Some(Replay::new(&world))));
+        world.add_observer(ReplayRecorder::new(recording.clone()));
+        world.set_serial(true);
+        for _ in 0..ticks {
+            world.tick();
+        }
+        let replay = recording.lock().unwrap().take().unwrap();
+        (replay, world)
     }
 
-    pub fn board<'a>(&'a self, background: &'a iced::widget::image::Handle) -> Board<'a> {
-        Board {
-            width: self.width as u32,
-            height: self.height as u32,
-            creatures: &self.creatures,
-            background,
+    #[test]
+    fn thread_count_does_not_change_the_world() {
+        let settings = WorldSettings {
+            seed: 45,
+            creature_generation_rate: 20,
+            mutation_rate: 0.5,
+            ..WorldSettings::default()
+        };
+        let (replay, world) = record(settings, 150);
+        assert!(!world.creatures.is_empty());
+
+        for threads in [1, 2, 8] {
+            let pool = rayon::ThreadPoolBuilder::new()
+                .num_threads(threads)
+                .build()
+                .unwrap();
+            let mut player = ReplayPlayer::new(replay.clone());
+            pool.install(|| while player.step() {});
+            assert_eq!(
+                player.world().fingerprint(),
+                world.fingerprint(),
+                "{threads} threads"
+            );
         }
     }
+    #[test]
+    fn same_seed_grows_the_same_world() {
+        let settings = WorldSettings {
+            seed: 7,
+            creature_generation_rate: 20,
+            mutation_rate: 0.5,
+            ..WorldSettings::default()
+        };
+        let run = || {
+            let mut world = World::new(192, 192, Tile::Lava, settings.clone());
+            for _ in 0..80 {
+                world.tick();
+            }
+            let library = world.genome_library(5);
+            world.seed_from_library(&library, 10);
+            for _ in 0..40 {
+                world.tick();
+            }
+            world
+        };
+        let world = run();
+        assert!(!world.creatures.is_empty());
+        assert_eq!(run().fingerprint(), world.fingerprint());
+    }
 }
@@ -0,0 +1,150 @@
+use serde::{Deserialize, Serialize};
+
+use super::{DeathCause, Position, Tile, World, observer::WorldEvent};
+
+/// Event that strikes every tile within `radius` of a point at once, at the end of a tick.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Catastrophe {
+    pub kind: CatastropheKind,
+    pub x: usize,
+    pub y: usize,
+    pub radius: usize,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum CatastropheKind {
+    /// Kills every creature
+    Meteor,
+    /// Removes all food
+    Famine,
+    /// Turns the ground into lava, killing the creatures on it
+    LavaFlood,
+    /// Grows food on all ground
+    Bloom,
+}
+
+impl CatastropheKind {
+    pub const ALL: [CatastropheKind; 4] = [
+        CatastropheKind::Meteor,
+        CatastropheKind::Famine,
+        CatastropheKind::LavaFlood,
+        CatastropheKind::Bloom,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            CatastropheKind::Meteor => "meteor",
+            CatastropheKind::Famine => "famine",
+            CatastropheKind::LavaFlood => "lava flood",
+            CatastropheKind::Bloom => "bloom",
+        }
+    }
+}
+
+/// Catastrophe that strikes during the given tick.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct ScheduledCatastrophe {
+    pub tick: u64,
+    pub catastrophe: Catastrophe,
+}
+
+impl Catastrophe {
+    /// Tiles of the world within the radius
+    fn positions(&self, width: usize, height: usize) -> Vec<Position> {
+        // Nothing past the world's longer side can be reached anyway
+        let radius = self.radius.min(width.max(height));
+        let rows = self.y.saturating_sub(radius)..self.y.saturating_add(radius + 1).min(height);
+        let columns = self.x.saturating_sub(radius)..self.x.saturating_add(radius + 1).min(width);
+        let mut positions = Vec::new();
+        for y in rows {
+            for x in columns.clone() {
+                let (dx, dy) = (x.abs_diff(self.x) as u64, y.abs_diff(self.y) as u64);
+                if dx * dx + dy * dy <= radius as u64 * radius as u64 {
+                    positions.push(Position { x, y });
+                }
+            }
+        }
+        positions
+    }
+}
+
+impl World {
+    /// Lets the catastrophe strike at the end of the next tick.
+    ///
+    /// Like the scheduled ones it is recorded with that tick, so replays repeat it.
+    pub fn trigger(&mut self, catastrophe: Catastrophe) {
+        self.queued_catastrophes.push(catastrophe);
+    }
+
+    /// Catastrophes scheduled for the current tick
+    pub(super) fn scheduled_catastrophes(&self) -> impl Iterator<Item = Catastrophe> {
+        self.settings
+            .catastrophes
+            .iter()
+            .filter(|scheduled| scheduled.tick == self.current_tick)
+            .map(|scheduled| scheduled.catastrophe.clone())
+    }
+
+    pub(super) fn strike(&mut self, catastrophe: &Catastrophe) {
+        self.notify_observers(|| WorldEvent::Catastrophe(catastrophe.clone()));
+
+        for position in catastrophe.positions(self.width, self.height) {
+            match catastrophe.kind {
+                CatastropheKind::Meteor => self.kill_by_catastrophe(&position),
+                CatastropheKind::Famine | CatastropheKind::Bloom => {
+                    let food = match catastrophe.kind {
+                        CatastropheKind::Bloom => 1.0,
+                        _ => 0.0,
+                    };
+                    if let Tile::Ground(data) = self.tiles.get(&position)
+                        && data.food != food
+                    {
+                        if let Tile::Ground(data) = self.tiles.get_mut(&position) {
+                            data.food = food;
+                        }
+                        self.dirty.mark(&position);
+                    }
+                }
+                CatastropheKind::LavaFlood => {
+                    self.kill_by_catastrophe(&position);
+                    if matches!(self.tiles.get(&position), Tile::Ground(_)) {
+                        *self.tiles.get_mut(&position) = Tile::Lava;
+                        self.dirty.mark(&position);
+                    }
+                }
+            }
+        }
+    }
+
+    fn kill_by_catastrophe(&mut self, position: &Position) {
+        if let Some(creature) = self.creatures.remove(position) {
+            self.notify(|| WorldEvent::Died {
+                id: creature.id,
+                position: position.clone(),
+                cause: DeathCause::Catastrophe,
+            });
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn meteor(x: usize, y: usize, radius: usize) -> Catastrophe {
+        Catastrophe {
+            kind: CatastropheKind::Meteor,
+            x,
+            y,
+            radius,
+        }
+    }
+
+    #[test]
+    fn positions_stay_within_the_world() {
+        assert_eq!(meteor(5, 4, usize::MAX).positions(10, 8).len(), 80);
+        assert_eq!(meteor(0, 0, 1).positions(10, 8).len(), 3);
+        assert_eq!(meteor(11, 3, 1).positions(10, 8).len(), 0);
+        assert_eq!(meteor(10, 3, 1).positions(10, 8), [Position { x: 9, y: 3 }]);
+    }
+}
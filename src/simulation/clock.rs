@@ -0,0 +1,95 @@
+use std::f32::consts::TAU;
+
+use serde::{Deserialize, Serialize};
+
+/// Cycles the environment goes through over the ticks, a long one for the seasons and a short one for day and
+/// night.
+///
+/// Each cycle follows a sine wave and scales food regrowth, movement costs and the heat of lava by
+/// `1 + amplitude * wave`, the factors of both cycles multiply. Without cycles the environment stays the same.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct WorldClock {
+    pub seasons: Option<Cycle>,
+    pub days: Option<Cycle>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum CycleKind {
+    Season,
+    Day,
+}
+
+impl CycleKind {
+    pub(super) fn randomize(rng: &mut fastrand::Rng) -> Self {
+        match rng.bool() {
+            true => CycleKind::Season,
+            false => CycleKind::Day,
+        }
+    }
+}
+
+/// One cycle of the clock. Amplitudes are the share a part of the environment rises above and falls below its
+/// average, negative ones make it peak in the other half of the cycle.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Cycle {
+    /// Ticks of a full cycle
+    pub period: u64,
+    pub food: f32,
+    pub movement: f32,
+    pub hazard: f32,
+}
+
+impl Cycle {
+    /// Position within the cycle, rising from 0.0 to 1.0
+    pub fn phase(&self, tick: u64) -> f32 {
+        if self.period == 0 {
+            return 0.0;
+        }
+        (tick % self.period) as f32 / self.period as f32
+    }
+
+    fn wave(&self, tick: u64) -> f32 {
+        (TAU * self.phase(tick)).sin()
+    }
+}
+
+/// Factors the clock scales each part of the environment by during a tick, 1.0 being the average.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Environment {
+    /// Scales the number of tiles food may grow back on
+    pub food: f32,
+    /// Scales the extra energy for stepping onto ground
+    pub movement: f32,
+    /// Scales the energy burned next to lava
+    pub hazard: f32,
+}
+
+impl Default for Environment {
+    fn default() -> Self {
+        Self {
+            food: 1.0,
+            movement: 1.0,
+            hazard: 1.0,
+        }
+    }
+}
+
+impl WorldClock {
+    pub fn cycle(&self, kind: CycleKind) -> Option<&Cycle> {
+        match kind {
+            CycleKind::Season => self.seasons.as_ref(),
+            CycleKind::Day => self.days.as_ref(),
+        }
+    }
+
+    pub fn environment(&self, tick: u64) -> Environment {
+        let mut environment = Environment::default();
+        for cycle in self.seasons.iter().chain(&self.days) {
+            let wave = cycle.wave(tick);
+            environment.food *= (1.0 + cycle.food * wave).max(0.0);
+            environment.movement *= (1.0 + cycle.movement * wave).max(0.0);
+            environment.hazard *= (1.0 + cycle.hazard * wave).max(0.0);
+        }
+        environment
+    }
+}
@@ -0,0 +1,103 @@
+use std::ops::Range;
+
+use super::Position;
+
+/// Edge length in tiles of the square blocks tiles are tracked in
+pub const DIRTY_BLOCK_SIZE: usize = 32;
+
+/// Blocks of tiles whose color changed since the regions were last taken.
+///
+/// The default value has no size and counts as entirely dirty.
+#[derive(Clone, Debug)]
+pub struct DirtyRegions {
+    width: usize,
+    height: usize,
+    blocks_per_row: usize,
+    blocks: Vec<bool>,
+    all: bool,
+}
+
+impl Default for DirtyRegions {
+    fn default() -> Self {
+        Self {
+            width: 0,
+            height: 0,
+            blocks_per_row: 0,
+            blocks: Vec::new(),
+            all: true,
+        }
+    }
+}
+
+impl DirtyRegions {
+    pub fn clean(width: usize, height: usize) -> Self {
+        let blocks_per_row = width.div_ceil(DIRTY_BLOCK_SIZE);
+        Self {
+            width,
+            height,
+            blocks_per_row,
+            blocks: vec![false; blocks_per_row * height.div_ceil(DIRTY_BLOCK_SIZE)],
+            all: false,
+        }
+    }
+
+    pub fn mark(&mut self, position: &Position) {
+        if self.all {
+            return;
+        }
+        let block =
+            position.y / DIRTY_BLOCK_SIZE * self.blocks_per_row + position.x / DIRTY_BLOCK_SIZE;
+        if let Some(dirty) = self.blocks.get_mut(block) {
+            *dirty = true;
+        }
+    }
+
+    pub fn mark_all(&mut self) {
+        self.all = true;
+    }
+
+    pub fn is_all(&self) -> bool {
+        self.all
+    }
+
+    pub fn contains(&self, block: &Block) -> bool {
+        self.all || self.blocks.get(block.index).copied().unwrap_or(true)
+    }
+
+    /// Every dirty block, meaningless if everything is dirty.
+    pub fn blocks(&self) -> impl Iterator<Item = Block> + '_ {
+        self.blocks
+            .iter()
+            .enumerate()
+            .filter(|(_, dirty)| **dirty)
+            .map(|(index, _)| Block::new(index, self.blocks_per_row, self.width, self.height))
+    }
+}
+
+/// Block of tiles, the last ones in a row or column may be cut off by the edge of the world.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Block {
+    /// Row by row, starting at the top left
+    pub index: usize,
+    pub columns: Range<usize>,
+    pub rows: Range<usize>,
+}
+
+impl Block {
+    fn new(index: usize, blocks_per_row: usize, width: usize, height: usize) -> Self {
+        let x = index % blocks_per_row * DIRTY_BLOCK_SIZE;
+        let y = index / blocks_per_row * DIRTY_BLOCK_SIZE;
+        Self {
+            index,
+            columns: x..(x + DIRTY_BLOCK_SIZE).min(width),
+            rows: y..(y + DIRTY_BLOCK_SIZE).min(height),
+        }
+    }
+
+    /// Every block of a world of the given size, in the order of their indices
+    pub fn all(width: usize, height: usize) -> impl Iterator<Item = Block> {
+        let blocks_per_row = width.div_ceil(DIRTY_BLOCK_SIZE);
+        let count = blocks_per_row * height.div_ceil(DIRTY_BLOCK_SIZE);
+        (0..count).map(move |index| Block::new(index, blocks_per_row, width, height))
+    }
+}
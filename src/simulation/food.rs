@@ -0,0 +1,311 @@
+use serde::{Deserialize, Serialize};
+
+use super::{Biome, Position, Tile, World, observer::WorldEvent};
+
+/// Times a regrowth attempt picks another tile when it lands on lava
+const LAVA_REDRAWS: usize = 8;
+/// Food a successful regrowth attempt adds to a tile
+const REGROWTH: f32 = 0.25;
+/// Food logistic growth treats a tile as carrying on top of its own, so fully grazed land grows back as well
+const BARREN_GROWTH: f32 = 0.05;
+/// Logistic growth only approaches a full tile, so above this it fills the tile up and lets the chunk settle
+const NEARLY_FULL: f32 = 0.99;
+/// Chance for food to spread to a tile without any food next to it
+const SEEDING_CHANCE: f32 = 0.05;
+
+/// How food grows back. Logistic growth acts on every tile, with the other models `food_regen_rate` tiles are
+/// picked every tick and each may grow food according to these.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct FoodGrowth {
+    pub model: GrowthModel,
+    /// Restricts regrowth to the surroundings of these points, it happens anywhere if there are none
+    pub sources: Vec<FoodSource>,
+    pub biome_rates: BiomeRates,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub enum GrowthModel {
+    /// Food grows back on every tile that is picked
+    #[default]
+    Uniform,
+    /// Every tile that is not full grows by `rate * (food + BARREN_GROWTH) * (1 - food)` each tick, slowly on
+    /// grazed land, fastest at half full and slowing down again towards a full tile
+    Logistic { rate: f32 },
+    /// Food grows next to food, the more the 4 neighbours carry, the likelier. Barren land is only seeded rarely.
+    Spread,
+}
+
+impl GrowthModel {
+    pub fn name(&self) -> String {
+        match self {
+            GrowthModel::Uniform => "uniform".to_string(),
+            GrowthModel::Logistic { rate } => format!("logistic at {rate:.2}"),
+            GrowthModel::Spread => "spreading".to_string(),
+        }
+    }
+}
+
+/// Square of tiles around a fixed point that food grows on.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct FoodSource {
+    pub x: usize,
+    pub y: usize,
+    pub radius: usize,
+}
+
+impl FoodSource {
+    fn contains(&self, position: &Position) -> bool {
+        position.x.abs_diff(self.x) <= self.radius && position.y.abs_diff(self.y) <= self.radius
+    }
+
+    fn random_position(&self, rng: &mut fastrand::Rng) -> Option<Position> {
+        let radius = self.radius as isize;
+        Position {
+            x: self.x,
+            y: self.y,
+        }
+        .offset(rng.isize(-radius..=radius), rng.isize(-radius..=radius))
+    }
+}
+
+/// Regrowth of each biome relative to the others.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct BiomeRates {
+    pub meadow: f32,
+    pub forest: f32,
+    pub desert: f32,
+}
+
+impl Default for BiomeRates {
+    fn default() -> Self {
+        Self {
+            meadow: 1.0,
+            forest: 1.0,
+            desert: 1.0,
+        }
+    }
+}
+
+impl BiomeRates {
+    fn rate(&self, biome: Biome) -> f32 {
+        match biome {
+            Biome::Meadow => self.meadow,
+            Biome::Forest => self.forest,
+            Biome::Desert => self.desert,
+        }
+    }
+
+    fn max(&self) -> f32 {
+        self.meadow.max(self.forest).max(self.desert).max(0.0)
+    }
+}
+
+impl World {
+    /// Lets food grow back according to the growth settings, scaled by the clock.
+    pub(super) fn grow_food(&mut self, rng: &mut fastrand::Rng) {
+        let growth = self.settings.food_growth.clone();
+        let factor = self.settings.clock.environment(self.current_tick).food;
+        match growth.model {
+            GrowthModel::Logistic { rate } => self.grow_logistic(&growth, rate * factor),
+            GrowthModel::Uniform | GrowthModel::Spread => {
+                self.grow_at_random(&growth, factor, rng);
+            }
+        }
+        self.tiles.settle();
+    }
+
+    /// Grows food on every tile of the active chunks that is not full and lies near a source, if there are any.
+    fn grow_logistic(&mut self, growth: &FoodGrowth, rate: f32) {
+        let chunks: Vec<_> = self.tiles.active_chunks().collect();
+        for (columns, rows) in chunks {
+            for y in rows {
+                for x in columns.clone() {
+                    let position = Position { x, y };
+                    let Tile::Ground(data) = self.tiles.get(&position) else {
+                        continue;
+                    };
+                    let food = data.food;
+                    if food >= 1.0
+                        || !growth.sources.is_empty()
+                            && !growth
+                                .sources
+                                .iter()
+                                .any(|source| source.contains(&position))
+                    {
+                        continue;
+                    }
+                    let amount = rate
+                        * growth.biome_rates.rate(data.biome)
+                        * (food + BARREN_GROWTH)
+                        * (1.0 - food);
+                    if amount <= 0.0 {
+                        continue;
+                    }
+                    let grown = if food + amount >= NEARLY_FULL {
+                        1.0
+                    } else {
+                        food + amount
+                    };
+
+                    if let Tile::Ground(data) = self.tiles.get_mut(&position) {
+                        data.food = grown;
+                    }
+                    self.dirty.mark(&position);
+                    self.notify_regrowth(position, grown - food);
+                }
+            }
+        }
+    }
+
+    /// Picks tiles at random and lets food grow back on them with the chance the model gives.
+    ///
+    /// Attempts are scaled by the fastest biome and `factor`, and tiles of slower biomes only take part in their
+    /// share of them.
+    fn grow_at_random(&mut self, growth: &FoodGrowth, factor: f32, rng: &mut fastrand::Rng) {
+        let max_rate = growth.biome_rates.max();
+        let attempts = self.settings.food_regen_rate as f32 * max_rate * factor;
+        let attempts = attempts as usize + (rng.f32() < attempts.fract()) as usize;
+
+        for _ in 0..attempts {
+            let Some(position) = self.pick_ground(&growth.sources, rng) else {
+                continue;
+            };
+            // Every tile of an inactive chunk is fully grown
+            if !self.tiles.is_active(&position) {
+                continue;
+            }
+            let Tile::Ground(data) = self.tiles.get(&position) else {
+                continue;
+            };
+            if data.food >= 1.0 {
+                continue;
+            }
+            let chance = growth.biome_rates.rate(data.biome) / max_rate
+                * self.growth_chance(growth.model, &position);
+            if rng.f32() >= chance {
+                continue;
+            }
+
+            let amount = REGROWTH.min(1.0 - data.food);
+            if let Tile::Ground(data) = self.tiles.get_mut(&position) {
+                data.food += amount;
+            }
+            self.dirty.mark(&position);
+            self.notify_regrowth(position, amount);
+        }
+    }
+
+    /// Regrowth only feeds the regrowth heatmap and observers, so the event is left out while neither is there.
+    fn notify_regrowth(&mut self, position: Position, amount: f32) {
+        if self.observed() || self.statistics.heatmap_window().is_some() {
+            self.notify(|| WorldEvent::FoodGrew { position, amount });
+        }
+    }
+
+    /// Random ground tile, near one of the sources if there are any.
+    fn pick_ground(&self, sources: &[FoodSource], rng: &mut fastrand::Rng) -> Option<Position> {
+        for _ in 0..LAVA_REDRAWS {
+            let position = match rng.choice(sources) {
+                Some(source) => source.random_position(rng),
+                None => Some(Position::randomize(self.width, self.height, rng)),
+            };
+            if let Some(position) = position
+                && matches!(self.get_tile(&position), Some(Tile::Ground(_)))
+            {
+                return Some(position);
+            }
+        }
+        None
+    }
+
+    /// Chance for food to grow on a tile that is not full, between 0 and 1
+    fn growth_chance(&self, model: GrowthModel, position: &Position) -> f32 {
+        let food = |dx, dy| {
+            position
+                .offset(dx, dy)
+                .and_then(|neighbour| self.get_tile(&neighbour))
+                .map_or(0.0, Tile::food)
+        };
+        match model {
+            GrowthModel::Uniform | GrowthModel::Logistic { .. } => 1.0,
+            GrowthModel::Spread => {
+                let neighbours: f32 = [(0, -1), (1, 0), (0, 1), (-1, 0)]
+                    .into_iter()
+                    .map(|(dx, dy)| food(dx, dy))
+                    .sum();
+                SEEDING_CHANCE + (1.0 - SEEDING_CHANCE) * neighbours / 4.0
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::simulation::{WorldSettings, clock::Cycle};
+
+    /// World whose ground was grazed bare
+    fn grazed(model: GrowthModel) -> World {
+        let settings = WorldSettings {
+            food_growth: FoodGrowth {
+                model,
+                ..FoodGrowth::default()
+            },
+            ..WorldSettings::default()
+        };
+        let mut world = World::new(64, 64, Tile::Lava, settings);
+        for y in 0..world.height {
+            for x in 0..world.width {
+                if let Tile::Ground(data) = world.tiles.get_mut(&Position { x, y }) {
+                    data.food = 0.0;
+                }
+            }
+        }
+        world
+    }
+
+    fn total_food(world: &World) -> f32 {
+        world.tiles.iter().map(Tile::food).sum()
+    }
+
+    #[test]
+    fn logistic_growth_fills_grazed_land() {
+        let mut world = grazed(GrowthModel::Logistic { rate: 0.05 });
+        let mut rng = fastrand::Rng::with_seed(0);
+        for _ in 0..200 {
+            world.grow_food(&mut rng);
+        }
+        assert!(world.tiles.iter().all(|tile| !tile.can_grow()));
+        assert_eq!(world.tiles.active_chunks().count(), 0);
+    }
+
+    #[test]
+    fn seasons_scale_regrowth() {
+        let mut world = grazed(GrowthModel::Logistic { rate: 0.05 });
+        world.settings.clock.seasons = Some(Cycle {
+            period: 4,
+            food: 1.0,
+            movement: 0.0,
+            hazard: 0.0,
+        });
+        let mut rng = fastrand::Rng::with_seed(0);
+        // The bottom of the season stops regrowth
+        world.current_tick = 3;
+        world.grow_food(&mut rng);
+        assert_eq!(total_food(&world), 0.0);
+
+        world.current_tick = 1;
+        world.grow_food(&mut rng);
+        assert!(total_food(&world) > 0.0);
+    }
+
+    #[test]
+    fn spread_seeds_grazed_land() {
+        let mut world = grazed(GrowthModel::Spread);
+        let mut rng = fastrand::Rng::with_seed(0);
+        for _ in 0..20 {
+            world.grow_food(&mut rng);
+        }
+        assert!(total_food(&world) > 0.0);
+    }
+}
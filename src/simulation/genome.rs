@@ -0,0 +1,110 @@
+use std::sync::Arc;
+
+use serde::{Deserialize, Serialize};
+
+use super::{Color, MAX_CREATURE_LIFETIME, neural_network::NeuralNetwork};
+
+pub const DEFAULT_ENERGY_CAPACITY: u16 = 1000;
+const MIN_LIFETIME: u64 = 100;
+const MIN_METABOLISM: f32 = 0.5;
+const MAX_METABOLISM: f32 = 2.0;
+const MIN_ENERGY_CAPACITY: u16 = 100;
+pub const DEFAULT_VIEW_RANGE: u8 = 5;
+const MIN_VIEW_RANGE: u8 = 2;
+const MAX_VIEW_RANGE: u8 = 8;
+
+/// Everything a creature passes on to its offspring
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Genome {
+    pub brain: Arc<NeuralNetwork>,
+    pub max_lifetime: u64,
+    /// Multiplier applied to the energy cost of every action
+    pub metabolism: f32,
+    pub energy_capacity: u16,
+    /// Color other creatures' eyes see on the creature's tile
+    pub color: Color,
+    /// Fraction of its own energy a parent hands to each offspring
+    pub offspring_share: f32,
+    /// Tiles the creature's rays and cones reach
+    pub view_range: u8,
+}
+
+impl Genome {
+    pub(crate) fn randomize(rng: &mut fastrand::Rng) -> Self {
+        Self {
+            brain: Arc::new(NeuralNetwork::randomize(rng)),
+            max_lifetime: MAX_CREATURE_LIFETIME,
+            metabolism: 1.0,
+            energy_capacity: DEFAULT_ENERGY_CAPACITY,
+            color: Color::randomize(rng),
+            offspring_share: 0.5,
+            view_range: rng.u8(MIN_VIEW_RANGE..=MAX_VIEW_RANGE),
+        }
+    }
+
+    /// Copy of this genome where every gene has a `mutation_rate` chance to change.
+    pub fn inherit(&self, mutation_rate: f32, rng: &mut fastrand::Rng) -> Self {
+        let mut child = self.clone();
+        let mutates = |rng: &mut fastrand::Rng| rng.f32() < mutation_rate;
+
+        if mutates(rng) {
+            child.brain = Arc::new(self.brain.mutate(rng));
+        }
+        if mutates(rng) {
+            child.max_lifetime = ((self.max_lifetime as f32 * drift(rng)) as u64)
+                .clamp(MIN_LIFETIME, MAX_CREATURE_LIFETIME * 2);
+        }
+        if mutates(rng) {
+            child.metabolism = (self.metabolism * drift(rng)).clamp(MIN_METABOLISM, MAX_METABOLISM);
+        }
+        if mutates(rng) {
+            child.energy_capacity = ((self.energy_capacity as f32 * drift(rng)) as u16)
+                .clamp(MIN_ENERGY_CAPACITY, u16::MAX);
+        }
+        if mutates(rng) {
+            child.color = self.color.mutate(rng);
+        }
+        if mutates(rng) {
+            child.offspring_share = (self.offspring_share * drift(rng)).clamp(0.05, 0.95);
+        }
+        if mutates(rng) {
+            // Too coarse for a factor, so the range moves a tile at a time
+            let step = if rng.bool() { 1 } else { -1 };
+            child.view_range = self
+                .view_range
+                .saturating_add_signed(step)
+                .clamp(MIN_VIEW_RANGE, MAX_VIEW_RANGE);
+        }
+
+        child
+    }
+
+    /// Like `inherit`, but the brain always changes.
+    pub fn mutant(&self, mutation_rate: f32, rng: &mut fastrand::Rng) -> Self {
+        let mut mutant = self.inherit(mutation_rate, rng);
+        mutant.brain = Arc::new(mutant.brain.mutate(rng));
+        mutant
+    }
+
+    /// Combines two genomes, taking each body gene from a random parent and crossing over the brains.
+    pub fn crossover(&self, other: &Genome, rng: &mut fastrand::Rng) -> Self {
+        Self {
+            brain: Arc::new(self.brain.crossover(&other.brain, rng)),
+            max_lifetime: pick(rng, self.max_lifetime, other.max_lifetime),
+            metabolism: pick(rng, self.metabolism, other.metabolism),
+            energy_capacity: pick(rng, self.energy_capacity, other.energy_capacity),
+            color: pick(rng, &self.color, &other.color).clone(),
+            offspring_share: pick(rng, self.offspring_share, other.offspring_share),
+            view_range: pick(rng, self.view_range, other.view_range),
+        }
+    }
+}
+
+fn pick<T>(rng: &mut fastrand::Rng, a: T, b: T) -> T {
+    if rng.bool() { a } else { b }
+}
+
+/// Random factor between 0.9 and 1.1 for numeric genes
+fn drift(rng: &mut fastrand::Rng) -> f32 {
+    0.9 + rng.f32() * 0.2
+}
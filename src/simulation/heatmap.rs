@@ -0,0 +1,270 @@
+use serde::{Deserialize, Serialize};
+
+use super::{DeathCause, Position, tile_grid::CHUNK_SIZE};
+
+/// Which statistic a heatmap overlay shows.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum HeatmapLayer {
+    /// How often a creature stood on the tile
+    Density,
+    /// Mean energy of the creatures that stood on the tile
+    Energy,
+    /// Deaths of the given cause, or of any cause
+    Deaths(Option<DeathCause>),
+    Births,
+    FoodRegrowth,
+    /// Ticks since food was last eaten on the tile, saturating at the window
+    SinceEaten,
+}
+
+impl HeatmapLayer {
+    pub const ALL: [HeatmapLayer; 12] = [
+        HeatmapLayer::Density,
+        HeatmapLayer::Energy,
+        HeatmapLayer::Deaths(None),
+        HeatmapLayer::Deaths(Some(DeathCause::OldAge)),
+        HeatmapLayer::Deaths(Some(DeathCause::Starvation)),
+        HeatmapLayer::Deaths(Some(DeathCause::Terrain)),
+        HeatmapLayer::Deaths(Some(DeathCause::Predation)),
+        HeatmapLayer::Deaths(Some(DeathCause::Decay)),
+        HeatmapLayer::Deaths(Some(DeathCause::Catastrophe)),
+        HeatmapLayer::Births,
+        HeatmapLayer::FoodRegrowth,
+        HeatmapLayer::SinceEaten,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            HeatmapLayer::Density => "creature density",
+            HeatmapLayer::Energy => "average energy",
+            HeatmapLayer::Deaths(None) => "deaths",
+            HeatmapLayer::Deaths(Some(DeathCause::OldAge)) => "deaths of old age",
+            HeatmapLayer::Deaths(Some(DeathCause::Starvation)) => "deaths by starvation",
+            HeatmapLayer::Deaths(Some(DeathCause::Terrain)) => "deaths by terrain",
+            HeatmapLayer::Deaths(Some(DeathCause::Predation)) => "deaths by predation",
+            HeatmapLayer::Deaths(Some(DeathCause::Decay)) => "decayed membranes",
+            HeatmapLayer::Deaths(Some(DeathCause::Catastrophe)) => "deaths by catastrophe",
+            HeatmapLayer::Births => "births",
+            HeatmapLayer::FoodRegrowth => "food regrowth",
+            HeatmapLayer::SinceEaten => "time since eaten",
+        }
+    }
+}
+
+/// Per-tile values that fade out exponentially, so they add up roughly the last `window` ticks.
+///
+/// Decay is applied lazily whenever a tile is touched or read, which keeps recording proportional to the
+/// number of events instead of the world size. The grid is only allocated once something is recorded.
+#[derive(Clone, Default, Serialize, Deserialize)]
+struct DecayingGrid {
+    values: Vec<f32>,
+    updated: Vec<u64>,
+    /// Chunks anything was recorded in, the values of all others are zero
+    touched: Vec<bool>,
+}
+
+impl DecayingGrid {
+    fn allocate(&mut self, len: usize, chunks: usize, tick: u64) {
+        self.values = vec![0.0; len];
+        self.updated = vec![tick; len];
+        self.touched = vec![false; chunks];
+    }
+
+    fn add(&mut self, index: usize, chunk: usize, amount: f32, tick: u64, window: u64) {
+        self.values[index] = self.get(index, tick, window) + amount;
+        self.updated[index] = tick;
+        self.touched[chunk] = true;
+    }
+
+    fn get(&self, index: usize, tick: u64, window: u64) -> f32 {
+        match self.values.get(index) {
+            Some(value) => {
+                let elapsed = tick.saturating_sub(self.updated[index]) as f32;
+                value * (-elapsed / window as f32).exp()
+            }
+            None => 0.0,
+        }
+    }
+}
+
+/// Where things happened in the world recently.
+///
+/// Nothing is recorded until a window is set.
+#[derive(Clone, Default, Serialize, Deserialize)]
+pub struct Heatmaps {
+    width: usize,
+    height: usize,
+    window: Option<u64>,
+    density: DecayingGrid,
+    energy: DecayingGrid,
+    deaths: [DecayingGrid; 6],
+    births: DecayingGrid,
+    regrowth: DecayingGrid,
+    last_eaten: Vec<u64>,
+}
+
+impl Heatmaps {
+    pub fn new(width: usize, height: usize, window: Option<u64>) -> Self {
+        Self {
+            width,
+            height,
+            window,
+            ..Default::default()
+        }
+    }
+
+    pub fn window(&self) -> Option<u64> {
+        self.window
+    }
+
+    /// Starts, stops or resizes the window. Values recorded so far fade out at the new rate.
+    pub fn set_window(&mut self, window: Option<u64>) {
+        self.window = window.map(|window| window.max(1));
+        if self.window.is_none() {
+            *self = Self::new(self.width, self.height, None);
+        }
+    }
+
+    fn record(
+        &mut self,
+        grid: impl FnOnce(&mut Self) -> &mut DecayingGrid,
+        position: &Position,
+        amount: f32,
+        tick: u64,
+    ) {
+        let Some(window) = self.window else {
+            return;
+        };
+        let (width, height) = (self.width, self.height);
+        let chunks_per_row = width.div_ceil(CHUNK_SIZE);
+        let grid = grid(self);
+        if grid.values.is_empty() {
+            grid.allocate(
+                width * height,
+                chunks_per_row * height.div_ceil(CHUNK_SIZE),
+                tick,
+            );
+        }
+        let index = position.y * width + position.x;
+        let chunk = position.y / CHUNK_SIZE * chunks_per_row + position.x / CHUNK_SIZE;
+        grid.add(index, chunk, amount, tick, window);
+    }
+
+    pub fn record_presence(&mut self, position: &Position, energy: u16, tick: u64) {
+        self.record(|heatmaps| &mut heatmaps.density, position, 1.0, tick);
+        self.record(
+            |heatmaps| &mut heatmaps.energy,
+            position,
+            energy as f32,
+            tick,
+        );
+    }
+
+    pub fn record_death(&mut self, position: &Position, cause: DeathCause, tick: u64) {
+        let cause = death_index(cause);
+        self.record(|heatmaps| &mut heatmaps.deaths[cause], position, 1.0, tick);
+    }
+
+    pub fn record_birth(&mut self, position: &Position, tick: u64) {
+        self.record(|heatmaps| &mut heatmaps.births, position, 1.0, tick);
+    }
+
+    pub fn record_regrowth(&mut self, position: &Position, amount: f32, tick: u64) {
+        self.record(|heatmaps| &mut heatmaps.regrowth, position, amount, tick);
+    }
+
+    pub fn record_meal(&mut self, position: &Position, tick: u64) {
+        if self.window.is_none() {
+            return;
+        }
+        if self.last_eaten.is_empty() {
+            self.last_eaten = vec![0; self.width * self.height];
+        }
+        self.last_eaten[position.y * self.width + position.x] = tick;
+    }
+
+    /// Intensity of the layer for every tile between 0 and 1, or `None` while nothing is recorded.
+    pub fn intensities(&self, layer: HeatmapLayer, tick: u64) -> Option<Vec<f32>> {
+        let window = self.window?;
+        let len = self.width * self.height;
+        let values: Vec<f32> = match layer {
+            HeatmapLayer::Density => self.values(&self.density, tick, window),
+            HeatmapLayer::Energy => {
+                let density = self.values(&self.density, tick, window);
+                let energy = self.values(&self.energy, tick, window);
+                density
+                    .iter()
+                    .zip(energy)
+                    .map(|(presence, energy)| {
+                        if *presence > 0.0 {
+                            energy / presence
+                        } else {
+                            0.0
+                        }
+                    })
+                    .collect()
+            }
+            HeatmapLayer::Deaths(Some(cause)) => {
+                self.values(&self.deaths[death_index(cause)], tick, window)
+            }
+            HeatmapLayer::Deaths(None) => self.deaths.iter().fold(vec![0.0; len], |sum, grid| {
+                sum.into_iter()
+                    .zip(self.values(grid, tick, window))
+                    .map(|(sum, value)| sum + value)
+                    .collect()
+            }),
+            HeatmapLayer::Births => self.values(&self.births, tick, window),
+            HeatmapLayer::FoodRegrowth => self.values(&self.regrowth, tick, window),
+            HeatmapLayer::SinceEaten => {
+                // Already relative to the window, no need to normalize
+                return Some(
+                    (0..len)
+                        .map(|index| {
+                            let eaten = self.last_eaten.get(index).copied().unwrap_or(0);
+                            (tick.saturating_sub(eaten) as f32 / window as f32).min(1.0)
+                        })
+                        .collect(),
+                );
+            }
+        };
+
+        let max = values.iter().copied().fold(0.0, f32::max);
+        if max <= 0.0 {
+            return Some(values);
+        }
+        Some(values.into_iter().map(|value| value / max).collect())
+    }
+
+    /// Current values of the grid, only the touched chunks are looked at.
+    fn values(&self, grid: &DecayingGrid, tick: u64, window: u64) -> Vec<f32> {
+        let chunks_per_row = self.width.div_ceil(CHUNK_SIZE);
+        let mut values = vec![0.0; self.width * self.height];
+        for (chunk, _) in grid
+            .touched
+            .iter()
+            .enumerate()
+            .filter(|(_, touched)| **touched)
+        {
+            let origin_x = chunk % chunks_per_row * CHUNK_SIZE;
+            let origin_y = chunk / chunks_per_row * CHUNK_SIZE;
+            for y in origin_y..(origin_y + CHUNK_SIZE).min(self.height) {
+                for x in origin_x..(origin_x + CHUNK_SIZE).min(self.width) {
+                    let index = y * self.width + x;
+                    values[index] = grid.get(index, tick, window);
+                }
+            }
+        }
+        values
+    }
+}
+
+fn death_index(cause: DeathCause) -> usize {
+    match cause {
+        DeathCause::OldAge => 0,
+        DeathCause::Starvation => 1,
+        DeathCause::Terrain => 2,
+        DeathCause::Predation => 3,
+        DeathCause::Decay => 4,
+        DeathCause::Catastrophe => 5,
+    }
+}
@@ -0,0 +1,189 @@
+use std::collections::{HashMap, HashSet, VecDeque};
+
+use serde::{Deserialize, Serialize};
+
+use super::observer::{WorldEvent, WorldObserver};
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct LineageRecord {
+    pub parent: Option<u64>,
+    /// The creature the parent mated with, if it had a second parent
+    pub partner: Option<u64>,
+    pub genome: Option<u64>,
+    pub born: u64,
+}
+
+/// Who descended from whom, keyed by creature ID.
+///
+/// Records of extinct branches are dropped by `prune`, but the per-genome descendant counts are kept forever.
+#[derive(Clone, Default, Serialize, Deserialize)]
+pub struct Lineage {
+    records: HashMap<u64, LineageRecord>,
+    genome_descendants: HashMap<u64, u64>,
+}
+
+impl Lineage {
+    /// Records a new individual. Descendants of a genome are counted whenever a creature with a parent carries it.
+    pub fn record_birth(
+        &mut self,
+        id: u64,
+        parent: Option<u64>,
+        partner: Option<u64>,
+        genome: Option<u64>,
+        born: u64,
+    ) {
+        if let (Some(_), Some(genome)) = (parent, genome) {
+            *self.genome_descendants.entry(genome).or_default() += 1;
+        }
+        self.records.insert(
+            id,
+            LineageRecord {
+                parent,
+                partner,
+                genome,
+                born,
+            },
+        );
+    }
+
+    pub fn get(&self, id: u64) -> Option<&LineageRecord> {
+        self.records.get(&id)
+    }
+
+    /// Returns the ancestors of a creature through both parents, nearest generations first.
+    pub fn ancestors(&self, id: u64) -> Vec<u64> {
+        let mut ancestors = Vec::new();
+        let mut seen = HashSet::new();
+        let mut queue = VecDeque::from_iter(self.parents(id));
+        while let Some(ancestor) = queue.pop_front() {
+            if seen.insert(ancestor) {
+                ancestors.push(ancestor);
+                queue.extend(self.parents(ancestor));
+            }
+        }
+        ancestors
+    }
+
+    fn parents(&self, id: u64) -> impl Iterator<Item = u64> {
+        self.records
+            .get(&id)
+            .into_iter()
+            .flat_map(|record| record.parent.into_iter().chain(record.partner))
+    }
+
+    /// Returns the genome that was copied into other creatures most often, together with that count.
+    pub fn most_successful_genome(&self) -> Option<(u64, u64)> {
+        self.genome_descendants
+            .iter()
+            .max_by_key(|(genome, descendants)| (**descendants, **genome))
+            .map(|(genome, descendants)| (*genome, *descendants))
+    }
+
+    /// Drops all records that are neither alive nor an ancestor of a living creature.
+    pub fn prune(&mut self, living: impl IntoIterator<Item = u64>) {
+        let mut keep = HashSet::new();
+        let mut pending: Vec<u64> = living.into_iter().collect();
+        while let Some(id) = pending.pop() {
+            if keep.insert(id) {
+                pending.extend(self.parents(id));
+            }
+        }
+        self.records.retain(|id, _| keep.contains(id));
+    }
+
+    /// Exports the recorded phylogeny in Newick format.
+    ///
+    /// Nodes are named `<creature id>` or `<creature id>_g<genome id>`, branch lengths are ticks between births.
+    /// Creatures without a known parent hang off a single unnamed root, mated ones off the parent that initiated it.
+    pub fn to_newick(&self) -> String {
+        let mut children: HashMap<u64, Vec<u64>> = HashMap::new();
+        let mut roots = Vec::new();
+        for (id, record) in &self.records {
+            match record
+                .parent
+                .filter(|parent| self.records.contains_key(parent))
+            {
+                Some(parent) => children.entry(parent).or_default().push(*id),
+                None => roots.push(*id),
+            }
+        }
+        roots.sort_unstable();
+        for siblings in children.values_mut() {
+            siblings.sort_unstable();
+        }
+
+        enum Visit {
+            Enter(u64),
+            Exit(u64),
+            Separator,
+        }
+
+        // Lineages can be thousands of generations deep, so this walks the tree without recursion
+        let mut newick = String::from("(");
+        let mut stack = Vec::new();
+        for (index, root) in roots.iter().enumerate().rev() {
+            stack.push(Visit::Enter(*root));
+            if index > 0 {
+                stack.push(Visit::Separator);
+            }
+        }
+
+        while let Some(visit) = stack.pop() {
+            match visit {
+                Visit::Separator => newick.push(','),
+                Visit::Enter(id) => match children.get(&id) {
+                    Some(siblings) => {
+                        newick.push('(');
+                        stack.push(Visit::Exit(id));
+                        for (index, child) in siblings.iter().enumerate().rev() {
+                            stack.push(Visit::Enter(*child));
+                            if index > 0 {
+                                stack.push(Visit::Separator);
+                            }
+                        }
+                    }
+                    None => self.write_newick_label(&mut newick, id),
+                },
+                Visit::Exit(id) => {
+                    newick.push(')');
+                    self.write_newick_label(&mut newick, id);
+                }
+            }
+        }
+
+        newick.push_str(");");
+        newick
+    }
+
+    fn write_newick_label(&self, newick: &mut String, id: u64) {
+        let record = &self.records[&id];
+        newick.push_str(&id.to_string());
+        if let Some(genome) = record.genome {
+            newick.push_str(&format!("_g{genome:x}"));
+        }
+        let parent_born = record
+            .parent
+            .and_then(|parent| self.records.get(&parent))
+            .map(|parent| parent.born)
+            .unwrap_or(record.born);
+        newick.push_str(&format!(":{}", record.born.saturating_sub(parent_born)));
+    }
+}
+
+impl WorldObserver for Lineage {
+    fn on_event(&mut self, tick: u64, event: &WorldEvent) {
+        match event {
+            WorldEvent::Spawned {
+                id,
+                parent,
+                partner,
+                genome,
+                ..
+            } => self.record_birth(*id, *parent, *partner, *genome, tick),
+            WorldEvent::DnaCopied {
+                source, id, genome, ..
+            } => self.record_birth(*id, Some(*source), None, *genome, tick),
+            _ => (),
+        }
+    }
+}
@@ -1,24 +1,37 @@
-use std::collections::HashSet;
+use std::{
+    collections::HashMap,
+    hash::{DefaultHasher, Hash, Hasher},
+    sync::Arc,
+    time::{Duration, Instant},
+};
 
 use arrayvec::ArrayVec;
+use serde::{Deserialize, Serialize};
 
-use super::{CardinalDirection, Color, Creature, INITIAL_CREATURE_ENERGY, Position, World};
+use super::{
+    CardinalDirection, Color, Creature, INITIAL_CREATURE_ENERGY, Position, Tile, World,
+    WorldSettings, clock::CycleKind, pheromone::PHEROMONE_KINDS,
+};
 
-#[derive(Clone)]
+pub use compiled::CompiledNetwork;
+
+mod compiled;
+
+#[derive(Clone, Debug, PartialEq, Hash, Serialize, Deserialize)]
 pub enum Rotation {
     Clockwise,
     CounterClockwise,
 }
 impl Rotation {
-    fn randomize() -> Rotation {
-        match fastrand::bool() {
+    fn randomize(rng: &mut fastrand::Rng) -> Rotation {
+        match rng.bool() {
             true => Rotation::Clockwise,
             false => Rotation::CounterClockwise,
         }
     }
 }
 
-#[derive(Clone)]
+#[derive(Clone, Debug, PartialEq, Hash, Serialize, Deserialize)]
 pub enum Action {
     Idle,
     Move(Location),
@@ -26,10 +39,15 @@ pub enum Action {
     Eat,
     CreateMembrane(Location),
     CopyDna(Location),
+    Attack(Location),
+    Mate(Location),
+    ShareEnergy(Location),
+    Signal(u8),
+    EmitPheromone(u8),
 }
 
 impl Action {
-    pub fn energy_cost(&self) -> u16 {
+    pub fn energy_cost(&self, settings: &WorldSettings) -> u16 {
         match self {
             Action::Idle => 1,
             Action::Move(_) => 3,
@@ -37,6 +55,11 @@ impl Action {
             Action::Eat => 2,
             Action::CreateMembrane(_) => INITIAL_CREATURE_ENERGY + 5,
             Action::CopyDna(_) => 10,
+            Action::Attack(_) => settings.attack_energy_cost,
+            Action::Mate(_) => 5,
+            Action::ShareEnergy(_) => 2,
+            Action::Signal(_) => 1,
+            Action::EmitPheromone(_) => 1,
         }
     }
 }
@@ -44,18 +67,77 @@ impl Action {
 const NEURON_COUNT: usize = 16;
 const MIN_GENERATED_NEURONS: usize = 6;
 const CONNECTION_COUNT: usize = 16;
-
+const SIGNAL_CHANNELS: u8 = 4;
+const MIN_CLOCK_PERIOD: u16 = 10;
+const MAX_CLOCK_PERIOD: u16 = 1000;
+/// Least amount of food eyes notice on a tile
+const VISIBLE_FOOD: f32 = 0.5;
+
+/// Only the neurons and connections are saved, the rest is derived from them again when loading.
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(from = "NetworkGenes")]
 pub struct NeuralNetwork {
     neurons: ArrayVec<Neuron, NEURON_COUNT>,
     connections: ArrayVec<NeuralConnection, CONNECTION_COUNT>,
+    #[serde(skip)]
+    genome_id: u64,
+    #[serde(skip)]
+    compiled: CompiledNetwork,
+}
+
+/// Different networks may share a genome ID, so equal IDs are confirmed by comparing the structure.
+impl PartialEq for NeuralNetwork {
+    fn eq(&self, other: &Self) -> bool {
+        self.genome_id == other.genome_id
+            && self.neurons == other.neurons
+            && self.connections == other.connections
+    }
+}
+
+/// Groups items by their network, in the order the networks first appear.
+///
+/// Networks are looked up by their genome ID and only grouped if they turn out to be equal.
+pub fn group_by_network<'a, T>(
+    items: impl IntoIterator<Item = (&'a Arc<NeuralNetwork>, T)>,
+) -> Vec<(&'a Arc<NeuralNetwork>, Vec<T>)> {
+    let mut groups: Vec<(&Arc<NeuralNetwork>, Vec<T>)> = Vec::new();
+    let mut by_genome_id: HashMap<u64, Vec<usize>> = HashMap::new();
+    for (network, item) in items {
+        let candidates = by_genome_id.entry(network.genome_id()).or_default();
+        let group = candidates.iter().copied().find(|index| {
+            let grouped = groups[*index].0;
+            Arc::ptr_eq(grouped, network) || grouped == network
+        });
+        match group {
+            Some(index) => groups[index].1.push(item),
+            None => {
+                candidates.push(groups.len());
+                groups.push((network, vec![item]));
+            }
+        }
+    }
+    groups
+}
+
+/// The saved part of a `NeuralNetwork`
+#[derive(Deserialize)]
+struct NetworkGenes {
+    neurons: ArrayVec<Neuron, NEURON_COUNT>,
+    connections: ArrayVec<NeuralConnection, CONNECTION_COUNT>,
+}
+
+impl From<NetworkGenes> for NeuralNetwork {
+    fn from(genes: NetworkGenes) -> Self {
+        Self::new(genes.neurons, genes.connections)
+    }
 }
 
 impl NeuralNetwork {
-    pub(crate) fn randomize() -> NeuralNetwork {
-        let neuron_count = fastrand::usize(MIN_GENERATED_NEURONS..=NEURON_COUNT);
+    pub(crate) fn randomize(rng: &mut fastrand::Rng) -> NeuralNetwork {
+        let neuron_count = rng.usize(MIN_GENERATED_NEURONS..=NEURON_COUNT);
         let mut neurons = ArrayVec::new();
         for _ in 0..neuron_count {
-            let neuron = Neuron::randomize();
+            let neuron = Neuron::randomize(rng);
             neurons.push(neuron);
         }
 
@@ -84,89 +166,300 @@ impl NeuralNetwork {
             .collect::<ArrayVec<_, NEURON_COUNT>>();
 
         if output_neurons.is_empty() || input_neurons.is_empty() {
-            return Self::randomize();
+            return Self::randomize(rng);
         }
 
-        let mut connections = HashSet::new();
+        // Kept in the order they were drawn in, so the same seed yields the same genome ID
+        let mut connections = ArrayVec::<_, CONNECTION_COUNT>::new();
         let min_tries = output_neurons.len();
         let connection_generation_tries =
-            fastrand::usize(min_tries..=CONNECTION_COUNT.min(output_neurons.len() * 2));
+            rng.usize(min_tries..=CONNECTION_COUNT.min(output_neurons.len() * 2));
         for _ in 0..connection_generation_tries {
-            let source = fastrand::u8(0..input_neurons.len() as u8);
-            let destination = fastrand::u8(0..output_neurons.len() as u8);
+            let source = rng.u8(0..input_neurons.len() as u8);
+            let destination = rng.u8(0..output_neurons.len() as u8);
             if input_neurons[source as usize] == output_neurons[destination as usize] {
                 continue;
             }
-            connections.insert(NeuralConnection {
+            let connection = NeuralConnection {
                 source,
                 destination,
-            });
+            };
+            if !connections.contains(&connection) {
+                connections.push(connection);
+            }
         }
 
+        Self::new(neurons, connections)
+    }
+
+    fn new(
+        neurons: ArrayVec<Neuron, NEURON_COUNT>,
+        connections: ArrayVec<NeuralConnection, CONNECTION_COUNT>,
+    ) -> Self {
+        let mut hasher = DefaultHasher::new();
+        neurons.hash(&mut hasher);
+        connections.hash(&mut hasher);
+        let genome_id = hasher.finish();
+        let compiled = CompiledNetwork::compile(&neurons, &connections);
+
         Self {
             neurons,
-            connections: connections.into_iter().collect(),
+            connections,
+            genome_id,
+            compiled,
+        }
+    }
+
+    /// Hash of the network's structure, identical networks share the same ID.
+    pub fn genome_id(&self) -> u64 {
+        self.genome_id
+    }
+
+    /// Form of the network used to decide on actions, built along with it
+    pub fn compiled(&self) -> &CompiledNetwork {
+        &self.compiled
+    }
+
+    pub fn neuron_count(&self) -> usize {
+        self.neurons.len()
+    }
+
+    pub fn connection_count(&self) -> usize {
+        self.connections.len()
+    }
+
+    /// Genetic distance between two networks, ranging from 0.0 for identical to 1.0 for unrelated networks.
+    ///
+    /// Neurons are compared slot by slot: a different neuron type counts fully, the same type with different parameters counts half.
+    /// Connections are compared as sets. Both parts are weighted equally.
+    pub fn distance(&self, other: &NeuralNetwork) -> f32 {
+        if self == other {
+            return 0.0;
+        }
+
+        let slots = self.neurons.len().max(other.neurons.len());
+        let mut neuron_difference = 0.0;
+        for index in 0..slots {
+            neuron_difference += match (self.neurons.get(index), other.neurons.get(index)) {
+                (Some(a), Some(b)) if a == b => 0.0,
+                (Some(a), Some(b)) if a.same_type(b) => 0.5,
+                _ => 1.0,
+            };
+        }
+        let neuron_distance = if slots == 0 {
+            0.0
+        } else {
+            neuron_difference / slots as f32
+        };
+
+        let shared = self
+            .connections
+            .iter()
+            .filter(|connection| other.connections.contains(connection))
+            .count();
+        let union = self.connections.len() + other.connections.len() - shared;
+        let connection_distance = if union == 0 {
+            0.0
+        } else {
+            (union - shared) as f32 / union as f32
+        };
+
+        (neuron_distance + connection_distance) / 2.0
+    }
+
+    /// Builds a child network taking each neuron slot from a random parent.
+    ///
+    /// Connections both parents share are always inherited, the others with a 50% chance.
+    /// Connections pointing past the child's last neuron are dropped.
+    pub fn crossover(&self, other: &NeuralNetwork, rng: &mut fastrand::Rng) -> NeuralNetwork {
+        let (longer, shorter) = if self.neurons.len() >= other.neurons.len() {
+            (self, other)
+        } else {
+            (other, self)
+        };
+
+        let mut neurons = ArrayVec::new();
+        for (index, neuron) in longer.neurons.iter().enumerate() {
+            match shorter.neurons.get(index) {
+                Some(other_neuron) if rng.bool() => neurons.push(other_neuron.clone()),
+                Some(_) => neurons.push(neuron.clone()),
+                // Surplus neurons of the longer parent are inherited as a block
+                None if index == shorter.neurons.len() && rng.bool() => break,
+                None => neurons.push(neuron.clone()),
+            }
+        }
+
+        let mut connections = ArrayVec::new();
+        for connection in self.connections.iter().chain(other.connections.iter()) {
+            if connections.is_full() {
+                break;
+            }
+            if connection.source as usize >= neurons.len()
+                || connection.destination as usize >= neurons.len()
+                || connections.contains(connection)
+            {
+                continue;
+            }
+            let shared =
+                self.connections.contains(connection) && other.connections.contains(connection);
+            if shared || rng.bool() {
+                connections.push(connection.clone());
+            }
+        }
+
+        Self::new(neurons, connections)
+    }
+
+    /// Copy of this network with one random change: a replaced neuron, a removed or an added connection.
+    pub fn mutate(&self, rng: &mut fastrand::Rng) -> NeuralNetwork {
+        let mut neurons = self.neurons.clone();
+        let mut connections = self.connections.clone();
+
+        match rng.u8(0..3) {
+            0 if !neurons.is_empty() => {
+                let index = rng.usize(0..neurons.len());
+                neurons[index] = Neuron::randomize(rng);
+            }
+            1 if !connections.is_empty() => {
+                connections.swap_remove(rng.usize(0..connections.len()));
+            }
+            _ if neurons.len() > 1 && !connections.is_full() => {
+                let source = rng.u8(0..neurons.len() as u8);
+                let destination = rng.u8(0..neurons.len() as u8);
+                let connection = NeuralConnection {
+                    source,
+                    destination,
+                };
+                if source != destination && !connections.contains(&connection) {
+                    connections.push(connection);
+                }
+            }
+            _ => (),
         }
+
+        Self::new(neurons, connections)
+    }
+
+    /// Short human readable listing of the network's neurons
+    pub fn describe(&self) -> String {
+        self.neurons
+            .iter()
+            .map(|neuron| format!("{neuron:?}"))
+            .collect::<Vec<_>>()
+            .join(", ")
     }
 }
 
-#[derive(Hash, Eq, PartialEq)]
+#[derive(Clone, Hash, Eq, PartialEq, Debug, Serialize, Deserialize)]
 pub struct NeuralConnection {
     source: u8,
     destination: u8,
 }
 
+#[derive(Clone, Debug, PartialEq, Hash, Serialize, Deserialize)]
 pub enum Neuron {
     Input(InputNeuron),
     Output(Action),
 }
 
 impl Neuron {
-    fn randomize() -> Self {
-        let neuron_type = fastrand::u8(0..=10);
+    fn randomize(rng: &mut fastrand::Rng) -> Self {
+        let neuron_type = rng.u8(0..=26);
         match neuron_type {
             0 => Self::Input(InputNeuron::AlwaysActive),
             1 => Self::Input(InputNeuron::Random),
             2 => {
-                let location = Location::randomize();
+                let location = Location::randomize(rng);
                 Self::Input(InputNeuron::Feeler(location))
             }
             3 => {
-                let location = match fastrand::u8(0..5) {
-                    0 => Some(Location::InFront),
-                    1 => Some(Location::Left),
-                    2 => Some(Location::Right),
-                    3 => Some(Location::Behind),
-                    4 => None,
-                    _ => unreachable!(),
-                };
-
-                let color = Color::randomize();
+                let location = Location::randomize_optional(rng);
+                let color = Color::randomize(rng);
                 Self::Input(InputNeuron::Eye(location, color))
             }
-            4 => Self::Input(InputNeuron::EnergySensor(fastrand::u16(0..u16::MAX))),
+            4 => Self::Input(InputNeuron::EnergySensor(rng.u16(0..u16::MAX))),
             5 => Self::Output(Action::Idle),
             6 => Self::Output(Action::Eat),
             7 => {
-                let location = Location::randomize();
+                let location = Location::randomize(rng);
                 Self::Output(Action::Move(location))
             }
             8 => {
-                let rotation = Rotation::randomize();
+                let rotation = Rotation::randomize(rng);
                 Self::Output(Action::Rotate(rotation))
             }
             9 => {
-                let location = Location::randomize();
+                let location = Location::randomize(rng);
                 Self::Output(Action::CreateMembrane(location))
             }
             10 => {
-                let location = Location::randomize();
+                let location = Location::randomize(rng);
                 Self::Output(Action::CopyDna(location))
             }
+            11 => {
+                let location = Location::randomize(rng);
+                Self::Output(Action::Attack(location))
+            }
+            12 => {
+                let location = Location::randomize(rng);
+                Self::Output(Action::ShareEnergy(location))
+            }
+            13 => Self::Output(Action::Signal(rng.u8(0..SIGNAL_CHANNELS))),
+            14 => {
+                let location = Location::randomize(rng);
+                let channel = rng.u8(0..SIGNAL_CHANNELS);
+                Self::Input(InputNeuron::SignalSensor(location, channel))
+            }
+            15 => Self::Output(Action::EmitPheromone(rng.u8(0..PHEROMONE_KINDS))),
+            16 => {
+                let kind = rng.u8(0..PHEROMONE_KINDS);
+                let location = Location::randomize(rng);
+                Self::Input(InputNeuron::ScentGradient(kind, location))
+            }
+            17 => {
+                let location = Location::randomize(rng);
+                let target = VisionTarget::randomize(rng);
+                Self::Input(InputNeuron::Ray(location, target))
+            }
+            18 => {
+                let location = Location::randomize(rng);
+                let target = VisionTarget::randomize(rng);
+                Self::Input(InputNeuron::Cone(location, target))
+            }
+            19 => Self::Input(InputNeuron::EnergyLevel),
+            20 => Self::Input(InputNeuron::AgeFraction),
+            21 => Self::Input(InputNeuron::FoodAmount(Location::randomize_optional(rng))),
+            22 => {
+                let location = Location::randomize_optional(rng);
+                let channel = ColorChannel::randomize(rng);
+                Self::Input(InputNeuron::TileColor(location, channel))
+            }
+            23 => Self::Input(InputNeuron::CrowdDensity),
+            24 => {
+                let period = rng.u16(MIN_CLOCK_PERIOD..=MAX_CLOCK_PERIOD);
+                Self::Input(InputNeuron::Clock(period))
+            }
+            25 => {
+                let location = Location::randomize(rng);
+                Self::Output(Action::Mate(location))
+            }
+            26 => Self::Input(InputNeuron::WorldPhase(CycleKind::randomize(rng))),
             _ => unreachable!(),
         }
     }
 
+    fn same_type(&self, other: &Neuron) -> bool {
+        match (self, other) {
+            (Neuron::Input(a), Neuron::Input(b)) => {
+                std::mem::discriminant(a) == std::mem::discriminant(b)
+            }
+            (Neuron::Output(a), Neuron::Output(b)) => {
+                std::mem::discriminant(a) == std::mem::discriminant(b)
+            }
+            _ => false,
+        }
+    }
+
     fn has_output(&self) -> bool {
         match self {
             Neuron::Input(_) => true,
@@ -182,15 +475,242 @@ impl Neuron {
     }
 }
 
+#[derive(Clone, Debug, PartialEq, Hash, Serialize, Deserialize)]
 pub enum InputNeuron {
     AlwaysActive,
     Random,
     Feeler(Location),
     Eye(Option<Location>, Color),
     EnergySensor(u16),
+    SignalSensor(Location, u8),
+    /// Pheromone concentration in the given direction relative to the creature's own tile
+    ScentGradient(u8, Location),
+    /// Looks along a straight line as far as the creature's view range gene reaches.
+    /// Outputs 1.0 for a match on the adjacent tile, fading towards 0.0 at the end of the range.
+    Ray(Location, VisionTarget),
+    /// Like `Ray`, but the view widens by one tile to each side every two tiles of distance.
+    Cone(Location, VisionTarget),
+    /// Own energy as a fraction of the energy capacity
+    EnergyLevel,
+    /// Fraction of the maximum lifetime already lived
+    AgeFraction,
+    /// Amount of food on a tile
+    FoodAmount(Option<Location>),
+    /// A single color channel of a tile, scaled to 0.0..=1.0
+    TileColor(Option<Location>, ColorChannel),
+    /// Fraction of the 8 surrounding tiles occupied by other creatures
+    CrowdDensity,
+    /// Position within a cycle of the given number of ticks, rising from 0.0 to 1.0, always 0.0 for 0 ticks
+    Clock(u16),
+    /// Position within the world clock's cycle, rising from 0.0 to 1.0, always 0.0 without that cycle
+    WorldPhase(CycleKind),
+}
+
+impl InputNeuron {
+    /// Value the neuron outputs for the creature at `position`, random neurons draw from `rng`
+    fn sense(
+        &self,
+        me: &Creature,
+        position: &Position,
+        world: &World,
+        rng: &mut fastrand::Rng,
+    ) -> f32 {
+        match self {
+            InputNeuron::AlwaysActive => 1.0,
+            InputNeuron::Random => rng.f32(),
+            InputNeuron::Feeler(feel_location) => {
+                let feel_position = me.relative_position(position, feel_location.clone());
+
+                if world.creatures.contains_key(&feel_position) {
+                    1.0
+                } else {
+                    0.0
+                }
+            }
+            InputNeuron::Eye(look_location, color) => {
+                // Other creatures hide the tile they stand on, looking down shows the ground
+                let seen_color = match look_location {
+                    Some(location) => {
+                        world.visible_color(&me.relative_position(position, *location))
+                    }
+                    None => world.get_tile(position).map(Tile::color),
+                };
+
+                match seen_color {
+                    Some(seen_color) if &seen_color >= color => 1.0,
+                    _ => 0.0,
+                }
+            }
+            InputNeuron::EnergySensor(limit) => {
+                if me.energy >= *limit {
+                    1.0
+                } else {
+                    0.0
+                }
+            }
+            InputNeuron::SignalSensor(sense_location, channel) => {
+                let sense_position = me.relative_position(position, *sense_location);
+
+                match world.creatures.get(&sense_position) {
+                    Some(other) if other.signal == Some(*channel) => 1.0,
+                    _ => 0.0,
+                }
+            }
+            InputNeuron::ScentGradient(kind, sense_location) => {
+                let sense_position = me.relative_position(position, *sense_location);
+
+                world.pheromones.get(*kind, &sense_position) - world.pheromones.get(*kind, position)
+            }
+            InputNeuron::Ray(look_location, target) | InputNeuron::Cone(look_location, target) => {
+                let cone = matches!(self, InputNeuron::Cone(..));
+                let direction = look_location.to_cardinal(me.rotation);
+                let range = me.view_range();
+
+                match scan(world, position, direction, *target, range, cone) {
+                    Some(distance) => 1.0 - (distance - 1) as f32 / range as f32,
+                    None => 0.0,
+                }
+            }
+            InputNeuron::EnergyLevel => {
+                let capacity = me.energy_capacity(&world.settings);
+                if capacity == 0 {
+                    // A creature that can't store any energy is always empty
+                    return 0.0;
+                }
+                (me.energy as f32 / capacity as f32).min(1.0)
+            }
+            InputNeuron::AgeFraction => {
+                let age = world.current_tick.saturating_sub(me.born);
+                (age as f32 / me.max_lifetime() as f32).min(1.0)
+            }
+            InputNeuron::FoodAmount(look_location) => {
+                let look_position = me.optional_relative_position(position, *look_location);
+
+                world.get_tile(&look_position).map_or(0.0, Tile::food)
+            }
+            InputNeuron::TileColor(look_location, channel) => {
+                let look_position = me.optional_relative_position(position, *look_location);
+
+                match world.get_tile(&look_position) {
+                    Some(tile) => channel.read(&tile.color()) as f32 / 255.0,
+                    None => 0.0,
+                }
+            }
+            InputNeuron::CrowdDensity => {
+                let mut neighbours = 0;
+                for dy in -1..=1 {
+                    for dx in -1..=1 {
+                        if dx == 0 && dy == 0 {
+                            continue;
+                        }
+                        if let Some(neighbour) = position.offset(dx, dy)
+                            && world.creatures.contains_key(&neighbour)
+                        {
+                            neighbours += 1;
+                        }
+                    }
+                }
+                neighbours as f32 / 8.0
+            }
+            InputNeuron::Clock(period) => {
+                // Never generated, but a saved world or library may still carry it
+                if *period == 0 {
+                    return 0.0;
+                }
+                (world.current_tick % *period as u64) as f32 / *period as f32
+            }
+            InputNeuron::WorldPhase(kind) => world
+                .settings
+                .clock
+                .cycle(*kind)
+                .map_or(0.0, |cycle| cycle.phase(world.current_tick)),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Hash, Serialize, Deserialize)]
+pub enum ColorChannel {
+    Red,
+    Green,
+    Blue,
+}
+
+impl ColorChannel {
+    fn randomize(rng: &mut fastrand::Rng) -> Self {
+        match rng.u8(0..3) {
+            0 => ColorChannel::Red,
+            1 => ColorChannel::Green,
+            _ => ColorChannel::Blue,
+        }
+    }
+
+    fn read(&self, color: &Color) -> u8 {
+        match self {
+            ColorChannel::Red => color.r,
+            ColorChannel::Green => color.g,
+            ColorChannel::Blue => color.b,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Hash, Serialize, Deserialize)]
+pub enum VisionTarget {
+    Creature,
+    Food,
+    Hazard,
 }
 
-#[derive(Clone, Copy)]
+impl VisionTarget {
+    fn randomize(rng: &mut fastrand::Rng) -> Self {
+        match rng.u8(0..3) {
+            0 => VisionTarget::Creature,
+            1 => VisionTarget::Food,
+            _ => VisionTarget::Hazard,
+        }
+    }
+
+    fn matches(&self, world: &World, position: &Position) -> bool {
+        match self {
+            VisionTarget::Creature => world.creatures.contains_key(position),
+            VisionTarget::Food => world
+                .get_tile(position)
+                .is_some_and(|tile| tile.food() >= VISIBLE_FOOD),
+            VisionTarget::Hazard => matches!(world.get_tile(position), Some(Tile::Lava)),
+        }
+    }
+}
+
+/// Returns the distance to the nearest tile matching `target`, scanning outwards from `position`.
+fn scan(
+    world: &World,
+    position: &Position,
+    direction: CardinalDirection,
+    target: VisionTarget,
+    range: u8,
+    cone: bool,
+) -> Option<u8> {
+    let (forward_x, forward_y) = direction.delta();
+    // Perpendicular axis, used to widen the cone
+    let (side_x, side_y) = (-forward_y, forward_x);
+
+    for distance in 1..=range {
+        let spread = if cone { distance as isize / 2 } else { 0 };
+        for side in -spread..=spread {
+            let dx = forward_x * distance as isize + side_x * side;
+            let dy = forward_y * distance as isize + side_y * side;
+            let Some(look_position) = position.offset(dx, dy) else {
+                continue;
+            };
+            if target.matches(world, &look_position) {
+                return Some(distance);
+            }
+        }
+    }
+
+    None
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Hash, Serialize, Deserialize)]
 pub enum Location {
     InFront,
     Left,
@@ -217,14 +737,22 @@ impl Location {
         }
     }
 
-    fn randomize() -> Self {
-        match fastrand::u8(0..4) {
+    fn randomize(rng: &mut fastrand::Rng) -> Self {
+        match rng.u8(0..4) {
             0 => Location::InFront,
             1 => Location::Left,
             2 => Location::Right,
             _ => Location::Behind,
         }
     }
+
+    /// Like `randomize`, but may also return `None` for the creature's own tile.
+    fn randomize_optional(rng: &mut fastrand::Rng) -> Option<Self> {
+        match rng.u8(0..5) {
+            4 => None,
+            _ => Some(Self::randomize(rng)),
+        }
+    }
 }
 
 pub struct NeuralTick {
@@ -232,43 +760,17 @@ pub struct NeuralTick {
 }
 
 impl NeuralTick {
-    pub fn seed(net: &NeuralNetwork, me: &Creature, position: &Position, world: &World) -> Self {
+    pub fn seed(
+        net: &NeuralNetwork,
+        me: &Creature,
+        position: &Position,
+        world: &World,
+        rng: &mut fastrand::Rng,
+    ) -> Self {
         let mut neuron_states = ArrayVec::new();
         for neuron in &net.neurons {
             let initial_output = match neuron {
-                Neuron::Input(input_neuron) => match input_neuron {
-                    InputNeuron::AlwaysActive => 1.0,
-                    InputNeuron::Random => fastrand::f32(),
-                    InputNeuron::Feeler(feel_location) => {
-                        let feel_position = me.relative_position(position, feel_location.clone());
-
-                        if world.creatures.contains_key(&feel_position) {
-                            1.0
-                        } else {
-                            0.0
-                        }
-                    }
-                    InputNeuron::Eye(look_location, color) => {
-                        let look_position = if let Some(location) = look_location {
-                            me.relative_position(position, location.clone())
-                        } else {
-                            position.clone()
-                        };
-
-                        if let Some(tile) = world.get_tile(&look_position) {
-                            if &tile.color() >= color { 1.0 } else { 0.0 }
-                        } else {
-                            0.0
-                        }
-                    }
-                    InputNeuron::EnergySensor(limit) => {
-                        if me.energy >= *limit {
-                            1.0
-                        } else {
-                            0.0
-                        }
-                    }
-                },
+                Neuron::Input(input_neuron) => input_neuron.sense(me, position, world, rng),
                 Neuron::Output(_action) => 0.0,
             };
 
@@ -311,3 +813,113 @@ pub struct NeuronValue {
     input: f32,
     output: f32,
 }
+
+/// Creatures sharing a network, with their positions
+type Members<'a> = [(&'a Position, &'a Creature)];
+
+/// Time each way of evaluating networks took to decide for all creatures of a world.
+pub struct EvaluatorComparison {
+    pub decisions: usize,
+    pub interpreted: Duration,
+    pub compiled: Duration,
+    pub batched: Duration,
+    /// Decisions of the compiled or batched evaluation that differ from the interpreter's
+    pub mismatches: usize,
+}
+
+/// Decides for every creature of the world `rounds` times with each evaluator, on the calling thread.
+///
+/// Every group of creatures sharing a network draws from a generator with the same seed with each evaluator,
+/// so all of them have to come to the same decisions.
+pub fn compare_evaluators(world: &World, rounds: u32) -> EvaluatorComparison {
+    let groups = world.brain_groups();
+    let decisions = groups.iter().map(|(_, members)| members.len()).sum();
+
+    let evaluate =
+        |decide: &dyn Fn(&NeuralNetwork, &Members, &mut fastrand::Rng) -> Vec<Action>| {
+            let start = Instant::now();
+            let mut first_round = Vec::new();
+            for round in 0..rounds {
+                for (group, (brain, members)) in groups.iter().enumerate() {
+                    let mut rng = fastrand::Rng::with_seed(group as u64);
+                    let actions = decide(brain, members, &mut rng);
+                    if round == 0 {
+                        first_round.extend(actions);
+                    }
+                }
+            }
+            (start.elapsed(), first_round)
+        };
+
+    let (interpreted, expected) = evaluate(&|brain, members, rng| {
+        members
+            .iter()
+            .map(|(position, me)| {
+                NeuralTick::seed(brain, me, position, world, rng).calculate_action(brain)
+            })
+            .collect()
+    });
+    let (compiled, compiled_actions) = evaluate(&|brain, members, rng| {
+        members
+            .iter()
+            .map(|(position, me)| brain.compiled().decide(me, position, world, rng))
+            .collect()
+    });
+    let (batched, batched_actions) =
+        evaluate(&|brain, members, rng| brain.compiled().decide_batch(members, world, rng));
+
+    let mismatches = expected
+        .iter()
+        .zip(&compiled_actions)
+        .zip(&batched_actions)
+        .filter(|((expected, compiled), batched)| expected != compiled || expected != batched)
+        .count();
+
+    EvaluatorComparison {
+        decisions,
+        interpreted,
+        compiled,
+        batched,
+        mismatches,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Two different networks made to share a genome ID
+    fn colliding() -> (Arc<NeuralNetwork>, Arc<NeuralNetwork>) {
+        let mut rng = fastrand::Rng::with_seed(0);
+        let first = NeuralNetwork::randomize(&mut rng);
+        let mut second = NeuralNetwork::randomize(&mut rng);
+        second.genome_id = first.genome_id;
+        (Arc::new(first), Arc::new(second))
+    }
+
+    #[test]
+    fn colliding_genome_ids_stay_apart() {
+        let (first, second) = colliding();
+        assert_ne!(first, second);
+        assert!(first.distance(&second) > 0.0);
+
+        let copy = Arc::new(NeuralNetwork::new(
+            first.neurons.clone(),
+            first.connections.clone(),
+        ));
+        let groups = group_by_network([(&first, 0), (&second, 1), (&copy, 2), (&first, 3)]);
+        let members: Vec<Vec<i32>> = groups.into_iter().map(|(_, members)| members).collect();
+        assert_eq!(members, [vec![0, 2, 3], vec![1]]);
+    }
+
+    #[test]
+    fn zero_period_clocks_stay_at_zero() {
+        let mut world = World::new(4, 4, Tile::Lava, WorldSettings::default());
+        world.current_tick = 17;
+        let me = Creature::new(0, CardinalDirection::North, None);
+        let position = Position { x: 0, y: 0 };
+        let mut rng = fastrand::Rng::with_seed(0);
+        let value = InputNeuron::Clock(0).sense(&me, &position, &world, &mut rng);
+        assert_eq!(value, 0.0);
+    }
+}
@@ -0,0 +1,369 @@
+use arrayvec::ArrayVec;
+
+use super::{Action, CONNECTION_COUNT, InputNeuron, NEURON_COUNT, NeuralConnection, Neuron};
+use crate::simulation::{Creature, Position, World};
+
+/// Fewer creatures than this are decided on one by one, as setting up the batch would take longer
+const MIN_BATCH_SIZE: usize = 8;
+
+/// A network flattened for evaluation: the sensors it reads and, for every output, which of them feed it.
+///
+/// Input neurons nothing listens to are left out, except random ones, so the same random numbers are drawn
+/// as when interpreting the network. Connections into input neurons or out of output neurons carry nothing
+/// and are dropped, as are outputs without any source. Sources keep the order of the connections, so
+/// impulses sum up to exactly the same values as in `NeuralTick`.
+#[derive(Debug, Default)]
+pub struct CompiledNetwork {
+    sensors: ArrayVec<InputNeuron, NEURON_COUNT>,
+    actions: ArrayVec<Action, NEURON_COUNT>,
+    /// End of each action's range in `sources`, the range starts where the previous one ended
+    source_ends: ArrayVec<u8, NEURON_COUNT>,
+    /// Indices into `sensors`
+    sources: ArrayVec<u8, CONNECTION_COUNT>,
+}
+
+impl CompiledNetwork {
+    pub(super) fn compile(neurons: &[Neuron], connections: &[NeuralConnection]) -> Self {
+        let mut compiled = Self::default();
+
+        let mut sensor_of = [None; NEURON_COUNT];
+        for (index, neuron) in neurons.iter().enumerate() {
+            let Neuron::Input(input) = neuron else {
+                continue;
+            };
+            let listened_to = connections
+                .iter()
+                .any(|connection| connection.source as usize == index);
+            if listened_to || matches!(input, InputNeuron::Random) {
+                sensor_of[index] = Some(compiled.sensors.len() as u8);
+                compiled.sensors.push(input.clone());
+            }
+        }
+
+        for (index, neuron) in neurons.iter().enumerate() {
+            let Neuron::Output(action) = neuron else {
+                continue;
+            };
+            let start = compiled.sources.len();
+            for connection in connections
+                .iter()
+                .filter(|connection| connection.destination as usize == index)
+            {
+                if let Some(sensor) = sensor_of[connection.source as usize] {
+                    compiled.sources.push(sensor);
+                }
+            }
+            if compiled.sources.len() > start {
+                compiled.actions.push(action.clone());
+                compiled.source_ends.push(compiled.sources.len() as u8);
+            }
+        }
+
+        compiled
+    }
+
+    /// Decides on the action of a single creature.
+    pub fn decide(
+        &self,
+        me: &Creature,
+        position: &Position,
+        world: &World,
+        rng: &mut fastrand::Rng,
+    ) -> Action {
+        let values: ArrayVec<f32, NEURON_COUNT> = self
+            .sensors
+            .iter()
+            .map(|sensor| sensor.sense(me, position, world, rng))
+            .collect();
+
+        let mut action = None;
+        let mut action_impulse = 0.0;
+        let mut start = 0;
+        for (index, end) in self.source_ends.iter().enumerate() {
+            let end = *end as usize;
+            let impulse = self.sources[start..end]
+                .iter()
+                .fold(0.0, |impulse, source| impulse + values[*source as usize]);
+            if impulse > action_impulse {
+                action = Some(index);
+                action_impulse = impulse;
+            }
+            start = end;
+        }
+
+        action.map_or(Action::Idle, |index| self.actions[index].clone())
+    }
+
+    /// Decides on the actions of creatures sharing this network, in the order they are given.
+    ///
+    /// Sensors are read creature by creature, as they draw random numbers in that order. The impulses are
+    /// then summed up one output at a time for the whole batch.
+    pub fn decide_batch(
+        &self,
+        members: &[(&Position, &Creature)],
+        world: &World,
+        rng: &mut fastrand::Rng,
+    ) -> Vec<Action> {
+        let count = members.len();
+        if count < MIN_BATCH_SIZE {
+            return members
+                .iter()
+                .map(|(position, me)| self.decide(me, position, world, rng))
+                .collect();
+        }
+        // One row per sensor, one column per creature
+        let mut values = vec![0.0; self.sensors.len() * count];
+        for (column, (position, me)) in members.iter().enumerate() {
+            for (row, sensor) in self.sensors.iter().enumerate() {
+                values[row * count + column] = sensor.sense(me, position, world, rng);
+            }
+        }
+
+        let mut actions = vec![None; count];
+        let mut action_impulses = vec![0.0; count];
+        let mut impulses = vec![0.0; count];
+        let mut start = 0;
+        for (index, end) in self.source_ends.iter().enumerate() {
+            let end = *end as usize;
+            impulses.fill(0.0);
+            for source in &self.sources[start..end] {
+                let row = &values[*source as usize * count..][..count];
+                for (impulse, value) in impulses.iter_mut().zip(row) {
+                    *impulse += value;
+                }
+            }
+            for ((impulse, action_impulse), action) in
+                impulses.iter().zip(&mut action_impulses).zip(&mut actions)
+            {
+                if impulse > action_impulse {
+                    *action = Some(index);
+                    *action_impulse = *impulse;
+                }
+            }
+            start = end;
+        }
+
+        actions
+            .into_iter()
+            .map(|action| action.map_or(Action::Idle, |index| self.actions[index].clone()))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::simulation::{
+        CardinalDirection, Tile, WorldSettings,
+        genome::Genome,
+        neural_network::{Location, NeuralNetwork, NeuralTick, Rotation, SIGNAL_CHANNELS},
+        pheromone::PHEROMONE_KINDS,
+    };
+
+    /// World with creatures of random genomes, energies and signals on tiles with random food, and pheromones
+    /// that peak on the creatures' own tiles.
+    fn random_world(seed: u64) -> World {
+        let mut rng = fastrand::Rng::with_seed(seed);
+        let mut world = World::new(48, 48, Tile::Lava, WorldSettings::default());
+        for _ in 0..40 {
+            let position = Position::randomize(world.width, world.height, &mut rng);
+            if let Tile::Ground(data) = world.tiles.get_mut(&position) {
+                data.food = rng.f32();
+            }
+            let genome = Genome::randomize(&mut rng);
+            let mut creature =
+                Creature::new(0, CardinalDirection::randomize(&mut rng), Some(genome));
+            creature.energy = rng.u16(..);
+            creature.signal = rng.bool().then(|| rng.u8(0..SIGNAL_CHANNELS));
+            for kind in 0..PHEROMONE_KINDS {
+                world.pheromones.emit(kind, &position, rng.f32());
+            }
+            world.creatures.insert(position, creature);
+        }
+        world.pheromones.step(0.2, 0.02);
+        world
+    }
+
+    /// Decides with the interpreter, the compiled network and in a batch for batches below and above
+    /// `MIN_BATCH_SIZE`, each starting from the same seed.
+    fn assert_equivalent(network: &NeuralNetwork, world: &World) {
+        let members: Vec<(&Position, &Creature)> = world.creatures.iter().collect();
+        for count in [1, MIN_BATCH_SIZE - 1, MIN_BATCH_SIZE, members.len()] {
+            let members = &members[..count];
+            let mut rng = fastrand::Rng::with_seed(count as u64);
+            let interpreted: Vec<Action> = members
+                .iter()
+                .map(|(position, me)| {
+                    NeuralTick::seed(network, me, position, world, &mut rng)
+                        .calculate_action(network)
+                })
+                .collect();
+            let mut rng = fastrand::Rng::with_seed(count as u64);
+            let compiled: Vec<Action> = members
+                .iter()
+                .map(|(position, me)| network.compiled().decide(me, position, world, &mut rng))
+                .collect();
+            let mut rng = fastrand::Rng::with_seed(count as u64);
+            let batched = network.compiled().decide_batch(members, world, &mut rng);
+
+            assert_eq!(interpreted, compiled, "{network:?} deciding for {count}");
+            assert_eq!(interpreted, batched, "{network:?} deciding for {count}");
+        }
+    }
+
+    fn connect(source: u8, destination: u8) -> NeuralConnection {
+        NeuralConnection {
+            source,
+            destination,
+        }
+    }
+
+    #[test]
+    fn random_networks_decide_like_the_interpreter() {
+        for seed in 0..200 {
+            let world = random_world(seed);
+            let mut rng = fastrand::Rng::with_seed(seed);
+            let mut network = NeuralNetwork::randomize(&mut rng);
+            // Mutations also connect into inputs and out of outputs
+            for _ in 0..rng.usize(0..20) {
+                network = network.mutate(&mut rng);
+            }
+            assert_equivalent(&network, &world);
+        }
+    }
+
+    #[test]
+    fn loaded_networks_are_compiled_again() {
+        let world = random_world(0);
+        let mut rng = fastrand::Rng::with_seed(0);
+        for _ in 0..50 {
+            let network = NeuralNetwork::randomize(&mut rng);
+            let bytes = bincode::serialize(&network).unwrap();
+            let loaded: NeuralNetwork = bincode::deserialize(&bytes).unwrap();
+            assert_eq!(loaded.genome_id(), network.genome_id());
+            assert_eq!(loaded.compiled().actions, network.compiled().actions);
+            assert_eq!(loaded.compiled().sources, network.compiled().sources);
+            assert_equivalent(&loaded, &world);
+        }
+    }
+
+    #[test]
+    fn edge_cases_decide_like_the_interpreter() {
+        let neurons = [
+            Neuron::Input(InputNeuron::AlwaysActive),
+            // Not listened to, but still draws a number
+            Neuron::Input(InputNeuron::Random),
+            Neuron::Input(InputNeuron::Random),
+            Neuron::Input(InputNeuron::ScentGradient(0, Location::InFront)),
+            Neuron::Output(Action::Eat),
+            Neuron::Output(Action::Idle),
+            Neuron::Output(Action::Move(Location::InFront)),
+            Neuron::Output(Action::Rotate(Rotation::Clockwise)),
+            Neuron::Output(Action::Signal(0)),
+        ];
+        let connections = [
+            // Eat and Move tie, the first one wins
+            connect(0, 4),
+            connect(0, 6),
+            connect(2, 5),
+            // Mostly negative, so it rarely beats doing nothing
+            connect(3, 7),
+            connect(3, 8),
+            connect(2, 8),
+            // Into inputs and out of outputs, carrying nothing
+            connect(4, 0),
+            connect(6, 1),
+            connect(4, 7),
+        ];
+        let network = NeuralNetwork::new(
+            neurons.into_iter().collect(),
+            connections.into_iter().collect(),
+        );
+
+        for seed in 0..20 {
+            let world = random_world(seed);
+            let negative_scent = world.creatures.iter().any(|(position, me)| {
+                InputNeuron::ScentGradient(0, Location::InFront).sense(
+                    me,
+                    position,
+                    &world,
+                    &mut fastrand::Rng::new(),
+                ) < 0.0
+            });
+            assert!(negative_scent);
+            assert_equivalent(&network, &world);
+        }
+    }
+}
+
+#[cfg(all(test, feature = "bench"))]
+mod benches {
+    extern crate test;
+
+    use test::Bencher;
+
+    use super::*;
+    use crate::simulation::{
+        CardinalDirection, Tile, WorldSettings, genome::Genome, neural_network::NeuralTick,
+    };
+
+    /// A thousand creatures sharing one random network
+    fn crowd() -> World {
+        let mut rng = fastrand::Rng::with_seed(46);
+        let mut world = World::new(200, 200, Tile::Lava, WorldSettings::default());
+        let genome = Genome::randomize(&mut rng);
+        while world.creatures.len() < 1000 {
+            let position = Position::randomize(world.width, world.height, &mut rng);
+            let creature = Creature::new(
+                0,
+                CardinalDirection::randomize(&mut rng),
+                Some(genome.clone()),
+            );
+            world.creatures.insert(position, creature);
+        }
+        world
+    }
+
+    fn members(world: &World) -> Vec<(&Position, &Creature)> {
+        world.creatures.iter().collect()
+    }
+
+    #[bench]
+    fn interpreted(bencher: &mut Bencher) {
+        let world = crowd();
+        let members = members(&world);
+        let mut rng = fastrand::Rng::with_seed(0);
+        bencher.iter(|| {
+            members
+                .iter()
+                .map(|(position, me)| {
+                    let brain = &me.genome.as_ref().unwrap().brain;
+                    NeuralTick::seed(brain, me, position, &world, &mut rng).calculate_action(brain)
+                })
+                .collect::<Vec<_>>()
+        });
+    }
+
+    #[bench]
+    fn compiled(bencher: &mut Bencher) {
+        let world = crowd();
+        let members = members(&world);
+        let brain = &members[0].1.genome.as_ref().unwrap().brain;
+        let mut rng = fastrand::Rng::with_seed(0);
+        bencher.iter(|| {
+            members
+                .iter()
+                .map(|(position, me)| brain.compiled().decide(me, position, &world, &mut rng))
+                .collect::<Vec<_>>()
+        });
+    }
+
+    #[bench]
+    fn batched(bencher: &mut Bencher) {
+        let world = crowd();
+        let members = members(&world);
+        let brain = &members[0].1.genome.as_ref().unwrap().brain;
+        let mut rng = fastrand::Rng::with_seed(0);
+        bencher.iter(|| brain.compiled().decide_batch(&members, &world, &mut rng));
+    }
+}
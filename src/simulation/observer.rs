@@ -0,0 +1,114 @@
+use super::{Creature, DeathCause, Position, catastrophe::Catastrophe, neural_network::Action};
+
+/// Something that happened while applying a tick.
+///
+/// Events are collected while a tick is applied and handed to observers once a partition of the world is done
+/// with it, partition by partition in a fixed order. Within a partition they keep the order they happened in.
+///
+/// The world's own lineage and statistics are kept up to date from these events as well. Events only observers
+/// care about, like the chosen actions, are not even built while none are registered.
+#[derive(Clone, Debug)]
+pub enum WorldEvent {
+    /// Emitted right before the action of the creature with the ID is applied
+    ActionChosen {
+        position: Position,
+        id: u64,
+        action: Action,
+    },
+    /// A randomly generated immigrant, emitted before the attempt to place it
+    RandomSpawn {
+        position: Position,
+        creature: Creature,
+    },
+    Spawned {
+        id: u64,
+        position: Position,
+        parent: Option<u64>,
+        /// The other parent of a mated creature
+        partner: Option<u64>,
+        genome: Option<u64>,
+    },
+    Died {
+        id: u64,
+        position: Position,
+        cause: DeathCause,
+    },
+    Moved {
+        id: u64,
+        from: Position,
+        to: Position,
+    },
+    Ate {
+        id: u64,
+        position: Position,
+        energy: u16,
+    },
+    /// Follows the `Spawned` event of the membrane
+    MembraneCreated {
+        parent: u64,
+        id: u64,
+        position: Position,
+    },
+    /// The destination was replaced by a new individual carrying the source's DNA
+    DnaCopied {
+        source: u64,
+        previous_id: u64,
+        id: u64,
+        genome: Option<u64>,
+        position: Position,
+    },
+    /// Food grew back on the tile by `amount`
+    FoodGrew {
+        position: Position,
+        amount: f32,
+    },
+    /// Emitted right before the catastrophe strikes, followed by the deaths it causes
+    Catastrophe(Catastrophe),
+    TickEnded,
+}
+
+pub trait WorldObserver: Send + Sync {
+    fn on_event(&mut self, tick: u64, event: &WorldEvent);
+}
+
+impl<F> WorldObserver for F
+where
+    F: FnMut(u64, &WorldEvent) + Send + Sync,
+{
+    fn on_event(&mut self, tick: u64, event: &WorldEvent) {
+        self(tick, event)
+    }
+}
+
+/// Observers registered with a world.
+///
+/// Cloning a world does not carry its observers over, the clone starts without any.
+#[derive(Default)]
+pub struct Observers(Vec<Box<dyn WorldObserver>>);
+
+impl Clone for Observers {
+    fn clone(&self) -> Self {
+        Self::default()
+    }
+}
+
+impl Observers {
+    pub fn push(&mut self, observer: Box<dyn WorldObserver>) {
+        self.0.push(observer);
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    /// Builds the event only if anybody is listening.
+    pub fn notify(&mut self, tick: u64, event: impl FnOnce() -> WorldEvent) {
+        if self.0.is_empty() {
+            return;
+        }
+        let event = event();
+        for observer in &mut self.0 {
+            observer.on_event(tick, &event);
+        }
+    }
+}
@@ -0,0 +1,589 @@
+use std::collections::HashMap;
+
+use super::{
+    CardinalDirection, Creature, DeathCause, Decision, Position, Tile, WorldSettings,
+    clock::Environment,
+    neural_network::{self, Action},
+    observer::WorldEvent,
+    tile_grid::{CHUNK_SIZE, TileGrid},
+};
+
+/// Number of phases the chunks are split into, so that chunks of the same phase never touch.
+/// Chunks take turns in a 2x2 checkerboard pattern.
+pub const PHASES: usize = 4;
+
+/// Phase in which the actions of creatures in the given chunk are applied
+pub fn phase(chunks_per_row: usize, chunk: usize) -> usize {
+    (chunk / chunks_per_row % 2) * 2 + chunk % chunks_per_row % 2
+}
+
+/// Chunk a position lies in
+pub fn chunk_of(chunks_per_row: usize, position: &Position) -> usize {
+    position.y / CHUNK_SIZE * chunks_per_row + position.x / CHUNK_SIZE
+}
+
+/// Chunks whose partition reaches the position: its own and, on the edge of a chunk, the neighbours across it
+pub fn reaching_chunks(
+    chunks_per_row: usize,
+    chunk_rows: usize,
+    position: &Position,
+) -> impl Iterator<Item = usize> {
+    let span = |coordinate: usize, count: usize| {
+        let chunk = coordinate / CHUNK_SIZE;
+        let offset = coordinate % CHUNK_SIZE;
+        let before = (offset == 0 && chunk > 0).then(|| chunk - 1);
+        let after = (offset == CHUNK_SIZE - 1 && chunk + 1 < count).then_some(chunk + 1);
+        [before, Some(chunk), after].into_iter().flatten()
+    };
+    let x = position.x;
+    span(position.y, chunk_rows).flat_map(move |row| {
+        span(x, chunks_per_row).map(move |column| row * chunks_per_row + column)
+    })
+}
+
+/// Seed for the randomness of a tick outside of the actions
+pub fn tick_seed(seed: u64, tick: u64) -> u64 {
+    seed ^ tick.wrapping_mul(0x9E37_79B9_7F4A_7C15)
+}
+
+/// Seed for the randomness of a single action, independent of the order actions are applied in
+pub fn action_seed(seed: u64, tick: u64, position: &Position) -> u64 {
+    job_seed(seed, tick, 0, pack(position))
+}
+
+/// Seed for the decisions of a batch of creatures, named by its first member. Kept apart from the action seeds,
+/// so a creature does not decide and act on the same numbers.
+pub fn decision_seed(seed: u64, tick: u64, first: &Position) -> u64 {
+    job_seed(seed, tick, 0xD1B5_4A32_D192_ED03, pack(first))
+}
+
+/// Seed for the `index`th randomly generated creature of a tick, independent of the thread generating it
+pub fn spawn_seed(seed: u64, tick: u64, index: usize) -> u64 {
+    job_seed(seed, tick, 0x94D0_49BB_1331_11EB, index as u64)
+}
+
+/// Seed for placing the copies of the `index`th genome of a library
+pub fn library_seed(seed: u64, tick: u64, index: usize) -> u64 {
+    job_seed(seed, tick, 0x2545_F491_4F6C_DD1D, index as u64)
+}
+
+/// Seed for one of the jobs of a tick, the salt keeps the different kinds of jobs apart
+fn job_seed(seed: u64, tick: u64, salt: u64, index: u64) -> u64 {
+    tick_seed(seed ^ salt, tick).wrapping_add(index.wrapping_mul(0xBF58_476D_1CE4_E5B9))
+}
+
+fn pack(position: &Position) -> u64 {
+    (position.y as u64) << 32 | position.x as u64
+}
+
+/// Whether an action can bring a new creature into the world, which needs an ID reserved for it
+pub fn may_give_birth(action: &Action) -> bool {
+    matches!(
+        action,
+        Action::CreateMembrane(_) | Action::CopyDna(_) | Action::Mate(_)
+    )
+}
+
+/// A region of the world that actions are applied in, independently of all other partitions.
+///
+/// An action only ever reaches the tiles next to its creature. A partition therefore owns the creatures of
+/// its chunk and of a one tile wide halo around it, and partitions whose halos don't overlap can run at the
+/// same time. Everything outside of that, like tile changes, births and events, is collected in `Effects`
+/// and committed to the world afterwards in a fixed order.
+pub struct Partition<'a> {
+    settings: &'a WorldSettings,
+    tick: u64,
+    /// How the clock scales movement costs and lava heat this tick
+    environment: Environment,
+    width: usize,
+    height: usize,
+    tiles: &'a TileGrid,
+    creatures: HashMap<Position, Creature>,
+    next_id: u64,
+    /// Whether observers are registered with the world, otherwise only events its lineage and statistics need
+    /// are collected
+    observed: bool,
+    /// Reseeded for every action, so no randomness is shared between threads or partitions
+    rng: fastrand::Rng,
+    eaten: Vec<Position>,
+    emissions: Vec<(u8, Position)>,
+    events: Vec<WorldEvent>,
+}
+
+/// What a partition changed outside of its creatures, in the order it happened.
+pub struct Effects {
+    pub creatures: HashMap<Position, Creature>,
+    /// First ID that was not handed out
+    pub next_id: u64,
+    /// Tiles whose food was eaten
+    pub eaten: Vec<Position>,
+    pub emissions: Vec<(u8, Position)>,
+    /// Births, deaths and, while observed, everything else that happened, for the lineage, statistics and observers
+    pub events: Vec<WorldEvent>,
+}
+
+impl<'a> Partition<'a> {
+    /// `next_id` is the first of the IDs reserved for the creatures born in this partition.
+    pub fn new(
+        settings: &'a WorldSettings,
+        tick: u64,
+        tiles: &'a TileGrid,
+        (width, height): (usize, usize),
+        creatures: HashMap<Position, Creature>,
+        next_id: u64,
+        observed: bool,
+    ) -> Self {
+        Self {
+            settings,
+            tick,
+            environment: settings.clock.environment(tick),
+            width,
+            height,
+            tiles,
+            creatures,
+            next_id,
+            observed,
+            rng: fastrand::Rng::with_seed(tick_seed(settings.seed, tick)),
+            eaten: Vec::new(),
+            emissions: Vec::new(),
+            events: Vec::new(),
+        }
+    }
+
+    pub fn finish(self) -> Effects {
+        Effects {
+            creatures: self.creatures,
+            next_id: self.next_id,
+            eaten: self.eaten,
+            emissions: self.emissions,
+            events: self.events,
+        }
+    }
+
+    fn notify(&mut self, event: impl FnOnce() -> WorldEvent) {
+        self.events.push(event());
+    }
+
+    /// Collects an event that only observers care about, building it only if there are any.
+    fn notify_observers(&mut self, event: impl FnOnce() -> WorldEvent) {
+        if self.observed {
+            self.events.push(event());
+        }
+    }
+
+    fn get_tile(&self, position: &Position) -> Option<&Tile> {
+        if position.x < self.width && position.y < self.height {
+            Some(self.tiles.get(position))
+        } else {
+            None
+        }
+    }
+
+    fn check_bounds(&self, position: &Position) -> bool {
+        position.x < self.width && position.y < self.height
+    }
+
+    /// Food left on the tile, nothing once a creature ate it this tick
+    fn food(&self, position: &Position) -> f32 {
+        if self.eaten.contains(position) {
+            return 0.0;
+        }
+        self.get_tile(position).map_or(0.0, Tile::food)
+    }
+
+    /// Energy burned this tick by standing next to lava
+    fn heat(&self, position: &Position) -> u16 {
+        if self.settings.lava_heat == 0 {
+            return 0;
+        }
+        let next_to_lava = (-1..=1)
+            .flat_map(|dy| (-1..=1).map(move |dx| (dx, dy)))
+            .filter_map(|(dx, dy)| position.offset(dx, dy))
+            .any(|neighbour| matches!(self.get_tile(&neighbour), Some(Tile::Lava)));
+        if next_to_lava {
+            (self.settings.lava_heat as f32 * self.environment.hazard).round() as u16
+        } else {
+            0
+        }
+    }
+
+    /// Applies every action in order, seeding the randomness of each from its position.
+    ///
+    /// Each action belongs to the creature with the given ID.
+    pub fn apply_actions(&mut self, actions: impl IntoIterator<Item = Decision>) {
+        for (position, id, action) in actions {
+            self.rng =
+                fastrand::Rng::with_seed(action_seed(self.settings.seed, self.tick, &position));
+            self.notify_observers(|| WorldEvent::ActionChosen {
+                position: position.clone(),
+                id,
+                action: action.clone(),
+            });
+            self.apply_action(&position, id, action);
+        }
+    }
+
+    fn apply_action(&mut self, position: &Position, id: u64, action: Action) {
+        let heat = self.heat(position);
+        // The creature might have been killed by an earlier action in this tick,
+        // and another one might have moved onto its tile since
+        let Some(creature) = self
+            .creatures
+            .get_mut(position)
+            .filter(|creature| creature.id == id)
+        else {
+            return;
+        };
+
+        if self.tick - creature.born > creature.max_lifetime() {
+            self.kill_creature(position, DeathCause::OldAge);
+            return;
+        }
+
+        if heat > 0 && creature.energy <= heat {
+            self.kill_creature(position, DeathCause::Terrain);
+            return;
+        }
+        creature.energy -= heat;
+
+        let mut action_cost = creature.energy_cost(&action, self.settings);
+        if let Action::Mate(_) = action {
+            // The partner pays the other half, and only if they mate
+            action_cost -= action_cost / 2;
+        }
+        let energy_cost = action_cost.saturating_add(creature.metabolize(self.settings));
+        if creature.energy >= energy_cost {
+            creature.energy = creature.energy.saturating_sub(energy_cost);
+        } else {
+            self.kill_creature(position, DeathCause::Starvation);
+            return;
+        }
+
+        match action {
+            Action::Idle => (),
+            Action::Eat => {
+                let food = self.food(position);
+                if food > 0.0 {
+                    let creature = self
+                        .creatures
+                        .get_mut(position)
+                        .expect("Creature was checked above");
+                    let energy = (self.settings.food_energy as f32 * food).round() as u16;
+                    creature.gain_energy(energy, self.settings);
+                    let id = creature.id;
+                    let energy = creature.energy;
+                    self.eaten.push(position.clone());
+                    self.notify(|| WorldEvent::Ate {
+                        id,
+                        position: position.clone(),
+                        energy,
+                    });
+                }
+            }
+            Action::Move(location) => {
+                let new_position = creature.relative_position(position, location);
+                if self.check_bounds(&new_position) {
+                    self.move_creature(position, new_position);
+                }
+            }
+            Action::Rotate(rotation) => {
+                creature.rotation.rotate(rotation);
+            }
+            Action::CreateMembrane(location) => {
+                let spawn_position = creature.relative_position(position, location);
+                let rotation = location.to_cardinal(creature.rotation);
+                self.create_membrane(position, spawn_position, rotation);
+            }
+            Action::CopyDna(location) => {
+                let copy_position = creature.relative_position(position, location);
+                self.copy_dna(position, &copy_position);
+            }
+            Action::Mate(location) => {
+                let partner_position = creature.relative_position(position, location);
+                self.mate(position, &partner_position, &action);
+            }
+            Action::Attack(location) => {
+                let target_position = creature.relative_position(position, location);
+                self.attack(position, &target_position);
+            }
+            Action::ShareEnergy(location) => {
+                let target_position = creature.relative_position(position, location);
+                self.share_energy(position, &target_position);
+            }
+            Action::Signal(channel) => {
+                creature.signal = Some(channel);
+            }
+            Action::EmitPheromone(kind) => {
+                self.emissions.push((kind, position.clone()));
+            }
+        }
+    }
+
+    fn move_creature(&mut self, old_position: &Position, new_position: Position) {
+        if self.creatures.contains_key(&new_position) {
+            return;
+        }
+        if let Some(mut creature) = self.creatures.remove(old_position) {
+            let tile = self
+                .get_tile(&new_position)
+                .expect("Coordinate should be correct");
+            if !tile.can_contain_creature() {
+                self.notify(|| WorldEvent::Died {
+                    id: creature.id,
+                    position: new_position,
+                    cause: DeathCause::Terrain,
+                });
+                return;
+            }
+            let movement_cost =
+                (tile.movement_cost(self.settings) as f32 * self.environment.movement).round();
+            creature.energy = creature.energy.saturating_sub(movement_cost as u16);
+            let id = creature.id;
+            self.creatures.insert(new_position.clone(), creature);
+            self.notify_observers(|| WorldEvent::Moved {
+                id,
+                from: old_position.clone(),
+                to: new_position,
+            });
+        }
+    }
+
+    /// Places a randomly generated immigrant if its tile is free.
+    pub fn spawn_random(&mut self, position: Position, creature: Creature) {
+        self.notify_observers(|| WorldEvent::RandomSpawn {
+            position: position.clone(),
+            creature: creature.clone(),
+        });
+        self.spawn_creature(position, creature, None, None);
+    }
+
+    /// Places the creature if the tile is free and returns its newly assigned ID.
+    fn spawn_creature(
+        &mut self,
+        position: Position,
+        mut creature: Creature,
+        parent: Option<u64>,
+        partner: Option<u64>,
+    ) -> Option<u64> {
+        let tile = self
+            .get_tile(&position)
+            .expect("Coordinate should be correct");
+        if !tile.can_contain_creature() || self.creatures.contains_key(&position) {
+            return None;
+        }
+        let id = self.next_id();
+        let genome = creature.genome_id();
+        creature.id = id;
+        self.creatures.insert(position.clone(), creature);
+        self.notify(|| WorldEvent::Spawned {
+            id,
+            position,
+            parent,
+            partner,
+            genome,
+        });
+        Some(id)
+    }
+
+    /// Spawns an empty creature, its starting energy is part of the action's cost.
+    fn create_membrane(
+        &mut self,
+        position: &Position,
+        spawn_position: Position,
+        rotation: CardinalDirection,
+    ) {
+        let Some(parent) = self.creatures.get_mut(position) else {
+            return;
+        };
+        parent.offspring += 1;
+        let parent_id = parent.id;
+
+        let membrane = Creature::new(self.tick, rotation, None);
+        if let Some(id) =
+            self.spawn_creature(spawn_position.clone(), membrane, Some(parent_id), None)
+        {
+            self.notify_observers(|| WorldEvent::MembraneCreated {
+                parent: parent_id,
+                id,
+                position: spawn_position,
+            });
+        }
+    }
+
+    fn next_id(&mut self) -> u64 {
+        let id = self.next_id;
+        self.next_id += 1;
+        id
+    }
+
+    /// Overwriting the DNA turns the destination into a new individual descending from the source.
+    fn copy_dna(&mut self, old_position: &Position, new_position: &Position) {
+        let id = self.next_id;
+        let [source, destination] = self
+            .creatures
+            .get_disjoint_mut([old_position, new_position]);
+        if let Some((source, destination)) = source.zip(destination) {
+            destination.genome = source
+                .genome
+                .as_ref()
+                .map(|genome| genome.inherit(self.settings.mutation_rate, &mut self.rng));
+            destination.species = source.species;
+            let previous_id = destination.id;
+            destination.id = id;
+            self.next_id += 1;
+            let genome = destination.genome_id();
+            let source = source.id;
+            self.notify(|| WorldEvent::DnaCopied {
+                source,
+                previous_id,
+                id,
+                genome,
+                position: new_position.clone(),
+            });
+        }
+    }
+
+    /// Places a child with a crossover of both parents' genomes next to the initiating parent.
+    /// Each parent hands its offspring share of energy to the child, and the partner pays its half of the
+    /// action's cost.
+    fn mate(&mut self, position: &Position, partner_position: &Position, action: &Action) {
+        if position == partner_position {
+            return;
+        }
+        let (Some(parent), Some(partner)) = (
+            self.creatures.get(position),
+            self.creatures.get(partner_position),
+        ) else {
+            return;
+        };
+        let (Some(genome), Some(partner_genome)) = (&parent.genome, &partner.genome) else {
+            return;
+        };
+        if genome.brain.distance(&partner_genome.brain) >= self.settings.mate_max_distance {
+            return;
+        }
+        let parent_share = parent.offspring_energy();
+        let partner_share = partner.offspring_energy();
+        let partner_cost = partner.energy_cost(action, self.settings) / 2;
+        // Both parents have to hand over some energy, and the partner has to afford its share on top of its cost
+        if parent_share == 0
+            || partner_share == 0
+            || partner.energy < partner_share.saturating_add(partner_cost)
+        {
+            return;
+        }
+        let start = CardinalDirection::randomize(&mut self.rng);
+        let Some((child_position, rotation)) = self.free_neighbour(position, start) else {
+            return;
+        };
+
+        let child_genome = genome
+            .crossover(partner_genome, &mut self.rng)
+            .inherit(self.settings.mutation_rate, &mut self.rng);
+        let parent_id = parent.id;
+        let partner_id = partner.id;
+        let species = parent.species;
+
+        let [parent, partner] = self
+            .creatures
+            .get_disjoint_mut([position, partner_position]);
+        if let Some((parent, partner)) = parent.zip(partner) {
+            parent.energy -= parent_share;
+            parent.offspring += 1;
+            partner.energy -= partner_share + partner_cost;
+            partner.offspring += 1;
+        }
+
+        let mut child = Creature::new(self.tick, rotation, Some(child_genome));
+        child.energy = 0;
+        child.gain_energy(parent_share.saturating_add(partner_share), self.settings);
+        child.species = species;
+        self.spawn_creature(child_position, child, Some(parent_id), Some(partner_id));
+    }
+
+    /// Finds an empty tile next to `position` that a creature could live on, starting at the `start` side.
+    fn free_neighbour(
+        &self,
+        position: &Position,
+        start: CardinalDirection,
+    ) -> Option<(Position, CardinalDirection)> {
+        let mut direction = start;
+        for _ in 0..4 {
+            let neighbour = position.cardinal(direction, 1);
+            let free = self
+                .get_tile(&neighbour)
+                .is_some_and(|tile| tile.can_contain_creature())
+                && !self.creatures.contains_key(&neighbour);
+            if free && &neighbour != position {
+                return Some((neighbour, direction));
+            }
+            direction.rotate(neural_network::Rotation::Clockwise);
+        }
+        None
+    }
+
+    fn attack(&mut self, attacker_position: &Position, target_position: &Position) {
+        if attacker_position == target_position {
+            return;
+        }
+        let [attacker, target] = self
+            .creatures
+            .get_disjoint_mut([attacker_position, target_position]);
+        if let Some((attacker, target)) = attacker.zip(target) {
+            let damage = target.energy.min(self.settings.attack_damage);
+            target.energy -= damage;
+            let gain = (damage as f32 * self.settings.attack_efficiency) as u16;
+            attacker.gain_energy(gain, self.settings);
+
+            if target.energy == 0 {
+                self.kill_creature(target_position, DeathCause::Predation);
+            }
+        }
+    }
+
+    fn share_energy(&mut self, giver_position: &Position, receiver_position: &Position) {
+        if giver_position == receiver_position {
+            return;
+        }
+        let [giver, receiver] = self
+            .creatures
+            .get_disjoint_mut([giver_position, receiver_position]);
+        if let Some((giver, receiver)) = giver.zip(receiver) {
+            let amount = giver.energy.min(self.settings.share_energy_amount);
+            giver.energy -= amount;
+            receiver.gain_energy(amount, self.settings);
+        }
+    }
+
+    fn kill_creature(&mut self, position: &Position, cause: DeathCause) {
+        if let Some(creature) = self.creatures.remove(position) {
+            self.notify(|| WorldEvent::Died {
+                id: creature.id,
+                position: position.clone(),
+                cause,
+            });
+        }
+    }
+
+    /// Membranes have no brain and never act, so they leak energy and expire here instead.
+    pub fn decay_membranes(&mut self) {
+        let mut decayed = Vec::new();
+        for (position, creature) in self.creatures.iter_mut() {
+            if creature.genome.is_some() {
+                continue;
+            }
+            creature.energy = creature
+                .energy
+                .saturating_sub(self.settings.membrane_energy_leak);
+            let age = self.tick - creature.born;
+            if creature.energy == 0 || age > self.settings.membrane_lifetime {
+                decayed.push(position.clone());
+            }
+        }
+
+        decayed.sort_unstable_by_key(|position| (position.y, position.x));
+        for position in decayed {
+            self.kill_creature(&position, DeathCause::Decay);
+        }
+    }
+}
@@ -0,0 +1,198 @@
+use std::sync::Arc;
+
+use rayon::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use super::{Position, tile_grid::CHUNK_SIZE};
+
+pub const PHEROMONE_KINDS: u8 = 2;
+/// Concentration below which a whole chunk counts as empty and stops being simulated
+const PHEROMONE_EPSILON: f32 = 1e-6;
+
+/// Per-tile chemical concentrations, one layer per pheromone kind.
+///
+/// Layers are split into the same square chunks as the tiles. Chunks without any pheromone are not stored
+/// and skipped by `step`, so large empty regions cost nothing. Chunks are shared between clones, each `step`
+/// replaces them with freshly computed ones instead of writing to them. Emissions are therefore held back
+/// and folded in during the next `step`.
+#[derive(Clone, Default, Serialize, Deserialize)]
+pub struct PheromoneField {
+    width: usize,
+    height: usize,
+    chunks_per_row: usize,
+    /// Chunks of every kind, `None` where the concentration is zero throughout
+    layers: Vec<Vec<Option<Arc<Vec<f32>>>>>,
+    /// Kind, position and amount of every emission since the last step
+    pending: Vec<(u8, Position, f32)>,
+}
+
+impl PheromoneField {
+    pub fn new(width: usize, height: usize) -> Self {
+        let chunks_per_row = width.div_ceil(CHUNK_SIZE);
+        let chunk_count = chunks_per_row * height.div_ceil(CHUNK_SIZE);
+        Self {
+            width,
+            height,
+            chunks_per_row,
+            layers: vec![vec![None; chunk_count]; PHEROMONE_KINDS as usize],
+            pending: Vec::new(),
+        }
+    }
+
+    /// Concentration as of the last step, emissions since then are not included yet.
+    pub fn get(&self, kind: u8, position: &Position) -> f32 {
+        if position.x < self.width && position.y < self.height {
+            value(
+                &self.layers[kind as usize],
+                self.chunks_per_row,
+                position.x,
+                position.y,
+            )
+        } else {
+            0.0
+        }
+    }
+
+    pub fn emit(&mut self, kind: u8, position: &Position, amount: f32) {
+        if position.x < self.width && position.y < self.height {
+            self.pending.push((kind, position.clone(), amount));
+        }
+    }
+
+    /// Number of chunks holding any pheromone of the given kind
+    pub fn active_chunks(&self, kind: u8) -> usize {
+        self.layers[kind as usize]
+            .iter()
+            .filter(|chunk| chunk.is_some())
+            .count()
+    }
+
+    /// Spreads every layer into its 4-neighbourhood and lets it evaporate.
+    /// Tiles on the world edge treat missing neighbours as their own value, so nothing leaks out.
+    ///
+    /// Only chunks holding pheromone, those receiving emissions and their neighbours, which the pheromone
+    /// may diffuse into, are computed. They are computed in parallel, reading the rim of the neighbouring
+    /// chunks as a halo.
+    pub fn step(&mut self, diffusion: f32, decay: f32) {
+        let width = self.width;
+        let height = self.height;
+        let chunks_per_row = self.chunks_per_row;
+        let keep = 1.0 - decay;
+
+        for (kind, layer) in self.layers.iter_mut().enumerate() {
+            let pending: Vec<_> = self
+                .pending
+                .iter()
+                .filter(|(k, ..)| *k as usize == kind)
+                .collect();
+            if pending.is_empty() && layer.iter().all(Option::is_none) {
+                continue;
+            }
+
+            let chunk_rows = layer.len() / chunks_per_row;
+            let mut active = vec![false; layer.len()];
+            let mut activate = |chunk: usize| {
+                let (chunk_x, chunk_y) = (chunk % chunks_per_row, chunk / chunks_per_row);
+                active[chunk] = true;
+                if chunk_x > 0 {
+                    active[chunk - 1] = true;
+                }
+                if chunk_x + 1 < chunks_per_row {
+                    active[chunk + 1] = true;
+                }
+                if chunk_y > 0 {
+                    active[chunk - chunks_per_row] = true;
+                }
+                if chunk_y + 1 < chunk_rows {
+                    active[chunk + chunks_per_row] = true;
+                }
+            };
+            for (chunk, values) in layer.iter().enumerate() {
+                if values.is_some() {
+                    activate(chunk);
+                }
+            }
+            for (_, position, _) in &pending {
+                activate(chunk_index(chunks_per_row, position.x, position.y));
+            }
+
+            let current = &*layer;
+            let mut next: Vec<Option<Vec<f32>>> = active
+                .par_iter()
+                .enumerate()
+                .map(|(chunk, active)| {
+                    if !active {
+                        return None;
+                    }
+                    let origin_x = chunk % chunks_per_row * CHUNK_SIZE;
+                    let origin_y = chunk / chunks_per_row * CHUNK_SIZE;
+                    let mut values = vec![0.0; CHUNK_SIZE * CHUNK_SIZE];
+                    for y in origin_y..(origin_y + CHUNK_SIZE).min(height) {
+                        for x in origin_x..(origin_x + CHUNK_SIZE).min(width) {
+                            let at = |x, y| value(current, chunks_per_row, x, y);
+                            let value = at(x, y);
+                            let north = if y > 0 { at(x, y - 1) } else { value };
+                            let south = if y + 1 < height { at(x, y + 1) } else { value };
+                            let west = if x > 0 { at(x - 1, y) } else { value };
+                            let east = if x + 1 < width { at(x + 1, y) } else { value };
+                            let neighbours = (north + south + west + east) * 0.25;
+                            values[(y - origin_y) * CHUNK_SIZE + x - origin_x] =
+                                keep * (value + diffusion * (neighbours - value));
+                        }
+                    }
+                    Some(values)
+                })
+                .collect();
+
+            // The step is linear, so an emission adds the same to the result as if it had been
+            // on the tile before diffusing
+            let mut add = |x: usize, y: usize, amount: f32| {
+                if let Some(values) = &mut next[chunk_index(chunks_per_row, x, y)] {
+                    values[local_index(x, y)] += amount;
+                }
+            };
+            for &&(_, Position { x, y }, amount) in &pending {
+                let neighbours = [
+                    (y > 0).then(|| (x, y - 1)),
+                    (y + 1 < height).then(|| (x, y + 1)),
+                    (x > 0).then(|| (x - 1, y)),
+                    (x + 1 < width).then(|| (x + 1, y)),
+                ];
+                let missing = neighbours.iter().filter(|n| n.is_none()).count() as f32;
+                add(
+                    x,
+                    y,
+                    keep * (amount + diffusion * (missing * amount * 0.25 - amount)),
+                );
+                for (x, y) in neighbours.into_iter().flatten() {
+                    add(x, y, keep * diffusion * amount * 0.25);
+                }
+            }
+
+            *layer = next
+                .into_iter()
+                .map(|values| {
+                    values
+                        .filter(|values| values.iter().any(|value| *value >= PHEROMONE_EPSILON))
+                        .map(Arc::new)
+                })
+                .collect();
+        }
+        self.pending.clear();
+    }
+}
+
+fn chunk_index(chunks_per_row: usize, x: usize, y: usize) -> usize {
+    y / CHUNK_SIZE * chunks_per_row + x / CHUNK_SIZE
+}
+
+fn local_index(x: usize, y: usize) -> usize {
+    y % CHUNK_SIZE * CHUNK_SIZE + x % CHUNK_SIZE
+}
+
+fn value(layer: &[Option<Arc<Vec<f32>>>], chunks_per_row: usize, x: usize, y: usize) -> f32 {
+    match &layer[chunk_index(chunks_per_row, x, y)] {
+        Some(values) => values[local_index(x, y)],
+        None => 0.0,
+    }
+}
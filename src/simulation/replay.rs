@@ -0,0 +1,236 @@
+use std::{
+    fs::File,
+    io::{BufReader, BufWriter},
+    path::Path,
+    sync::{Arc, Mutex},
+};
+
+use serde::{Deserialize, Serialize};
+
+use super::{
+    Creature, Decision, Position, World,
+    catastrophe::Catastrophe,
+    observer::{WorldEvent, WorldObserver},
+};
+
+/// Ticks between two worlds the player keeps in memory to speed up seeking backwards
+const PLAYER_CHECKPOINT_INTERVAL: u64 = 100;
+
+/// Everything that happened in a tick that can't be derived from the world's seed
+#[derive(Clone, Default, Serialize, Deserialize)]
+pub struct TickRecord {
+    pub actions: Vec<Decision>,
+    pub spawns: Vec<(Position, Creature)>,
+    pub catastrophes: Vec<Catastrophe>,
+}
+
+/// The world at the start of a recording, followed by a record for every tick since.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct Replay {
+    initial: World,
+    ticks: Vec<TickRecord>,
+}
+
+impl Replay {
+    pub fn new(initial: &World) -> Self {
+        Self {
+            initial: initial.clone(),
+            ticks: Vec::new(),
+        }
+    }
+
+    pub fn first_tick(&self) -> u64 {
+        self.initial.current_tick
+    }
+
+    pub fn last_tick(&self) -> u64 {
+        self.first_tick() + self.ticks.len() as u64
+    }
+
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<(), bincode::Error> {
+        let file = BufWriter::new(File::create(path)?);
+        bincode::serialize_into(file, self)
+    }
+
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, bincode::Error> {
+        let file = BufReader::new(File::open(path)?);
+        bincode::deserialize_from(file)
+    }
+}
+
+/// Observer that appends every tick to a shared replay, as long as one is set.
+pub struct ReplayRecorder {
+    replay: Arc<Mutex<Option<Replay>>>,
+    current: TickRecord,
+}
+
+impl ReplayRecorder {
+    /// Recording starts once a `Replay` of the world is put into `replay`, which must happen between two ticks.
+    pub fn new(replay: Arc<Mutex<Option<Replay>>>) -> Self {
+        Self {
+            replay,
+            current: TickRecord::default(),
+        }
+    }
+}
+
+impl WorldObserver for ReplayRecorder {
+    fn on_event(&mut self, _tick: u64, event: &WorldEvent) {
+        match event {
+            WorldEvent::ActionChosen {
+                position,
+                id,
+                action,
+            } => {
+                self.current
+                    .actions
+                    .push((position.clone(), *id, action.clone()));
+            }
+            WorldEvent::RandomSpawn { position, creature } => {
+                self.current
+                    .spawns
+                    .push((position.clone(), creature.clone()));
+            }
+            WorldEvent::Catastrophe(catastrophe) => {
+                self.current.catastrophes.push(catastrophe.clone());
+            }
+            WorldEvent::TickEnded => {
+                let record = std::mem::take(&mut self.current);
+                if let Some(replay) = self.replay.lock().expect("Recording panicked").as_mut() {
+                    replay.ticks.push(record);
+                }
+            }
+            _ => (),
+        }
+    }
+}
+
+/// Rebuilds the world of any recorded tick.
+pub struct ReplayPlayer {
+    replay: Replay,
+    world: World,
+    checkpoints: Vec<World>,
+}
+
+impl ReplayPlayer {
+    pub fn new(replay: Replay) -> Self {
+        let world = replay.initial.clone();
+        Self {
+            replay,
+            world,
+            checkpoints: Vec::new(),
+        }
+    }
+
+    pub fn replay(&self) -> &Replay {
+        &self.replay
+    }
+
+    pub fn world(&self) -> &World {
+        &self.world
+    }
+
+    /// Advances by one tick, returns false at the end of the recording.
+    pub fn step(&mut self) -> bool {
+        let index = (self.world.current_tick - self.replay.first_tick()) as usize;
+        let Some(record) = self.replay.ticks.get(index) else {
+            return false;
+        };
+        self.world.replay_tick(record);
+
+        let elapsed = self.world.current_tick - self.replay.first_tick();
+        let checkpoint = (elapsed / PLAYER_CHECKPOINT_INTERVAL) as usize;
+        if elapsed.is_multiple_of(PLAYER_CHECKPOINT_INTERVAL) && checkpoint > self.checkpoints.len()
+        {
+            self.checkpoints.push(self.world.clone());
+        }
+        true
+    }
+
+    /// Jumps to the given tick, clamped to the recorded range.
+    ///
+    /// Going backwards restarts from the closest checkpoint before the target.
+    pub fn seek(&mut self, tick: u64) {
+        let tick = tick.clamp(self.replay.first_tick(), self.replay.last_tick());
+        if tick < self.world.current_tick {
+            let elapsed = tick - self.replay.first_tick();
+            let checkpoint =
+                ((elapsed / PLAYER_CHECKPOINT_INTERVAL) as usize).min(self.checkpoints.len());
+            self.world = match checkpoint.checked_sub(1) {
+                Some(index) => self.checkpoints[index].clone(),
+                None => self.replay.initial.clone(),
+            };
+        }
+        while self.world.current_tick < tick && self.step() {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::simulation::{
+        Tile, WorldSettings,
+        catastrophe::{CatastropheKind, ScheduledCatastrophe},
+    };
+
+    fn catastrophe(kind: CatastropheKind, radius: usize) -> Catastrophe {
+        Catastrophe {
+            kind,
+            x: 60,
+            y: 60,
+            radius,
+        }
+    }
+
+    #[test]
+    fn saved_replays_rebuild_the_recorded_world() {
+        let settings = WorldSettings {
+            seed: 38,
+            creature_generation_rate: 10,
+            catastrophes: vec![ScheduledCatastrophe {
+                tick: 30,
+                catastrophe: catastrophe(CatastropheKind::Bloom, 40),
+            }],
+            ..WorldSettings::default()
+        };
+        let mut world = World::new(128, 128, Tile::Lava, settings);
+        let recording = Arc::new(Mutex::new(Some(Replay::new(&world))));
+        world.add_observer(ReplayRecorder::new(recording.clone()));
+
+        for tick in 1..=150 {
+            match tick {
+                50 => world.trigger(catastrophe(CatastropheKind::Meteor, 10)),
+                80 => {
+                    let library = world.genome_library(3);
+                    assert!(!library.genomes.is_empty());
+                    world.seed_from_library(&library, 20);
+                }
+                110 => world.trigger(catastrophe(CatastropheKind::Famine, 30)),
+                _ => (),
+            }
+            world.tick();
+        }
+
+        let replay = recording.lock().unwrap().take().unwrap();
+        let path = std::env::temp_dir().join(format!("replay-{}.bin", std::process::id()));
+        replay.save(&path).unwrap();
+        let loaded = Replay::load(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(loaded.ticks[29].catastrophes.len(), 1);
+        // Triggered and seeded before a tick, so they are part of its record
+        assert_eq!(loaded.ticks[49].catastrophes.len(), 1);
+        assert_eq!(loaded.ticks[79].spawns.len(), 10 + 3 * 20);
+        assert_eq!(loaded.ticks[109].catastrophes.len(), 1);
+
+        let mut player = ReplayPlayer::new(loaded);
+        player.seek(player.replay().last_tick());
+        assert_eq!(player.world().current_tick(), world.current_tick());
+        assert_eq!(player.world().fingerprint(), world.fingerprint());
+
+        // Seeking back restarts from a checkpoint and has to end up in the same world again
+        player.seek(20);
+        player.seek(player.replay().last_tick());
+        assert_eq!(player.world().fingerprint(), world.fingerprint());
+    }
+}
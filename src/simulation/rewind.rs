@@ -0,0 +1,61 @@
+use std::collections::VecDeque;
+
+use super::World;
+
+/// Ring buffer of world checkpoints taken every `interval` ticks.
+///
+/// Once `capacity` checkpoints are kept, the oldest one is dropped for every new one.
+/// Brains are behind an `Arc`, so checkpoints share them with the live world instead of copying them.
+pub struct RewindBuffer {
+    interval: u64,
+    capacity: usize,
+    checkpoints: VecDeque<World>,
+}
+
+impl RewindBuffer {
+    pub fn new(interval: u64, capacity: usize) -> Self {
+        Self {
+            interval,
+            capacity,
+            checkpoints: VecDeque::with_capacity(capacity),
+        }
+    }
+
+    /// Takes a checkpoint if the world is on the interval, call after every tick.
+    pub fn record(&mut self, world: &World) {
+        if !world.current_tick().is_multiple_of(self.interval) || self.capacity == 0 {
+            return;
+        }
+        if self
+            .checkpoints
+            .back()
+            .is_some_and(|last| last.current_tick() >= world.current_tick())
+        {
+            return;
+        }
+        if self.checkpoints.len() == self.capacity {
+            self.checkpoints.pop_front();
+        }
+        self.checkpoints.push_back(world.clone());
+    }
+
+    pub fn len(&self) -> usize {
+        self.checkpoints.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.checkpoints.is_empty()
+    }
+
+    pub fn get(&self, index: usize) -> Option<&World> {
+        self.checkpoints.get(index)
+    }
+
+    /// Returns a copy of the checkpoint to continue from and forgets every later checkpoint,
+    /// as they belong to the run that is being abandoned. The copy has no observers registered.
+    pub fn fork(&mut self, index: usize) -> Option<World> {
+        let world = self.checkpoints.get(index)?.clone();
+        self.checkpoints.truncate(index + 1);
+        Some(world)
+    }
+}
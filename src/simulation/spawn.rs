@@ -0,0 +1,191 @@
+use std::{
+    fs::File,
+    io::{BufReader, BufWriter},
+    path::Path,
+};
+
+use rayon::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use super::{
+    CardinalDirection, Creature, Position, World,
+    genome::Genome,
+    neural_network::group_by_network,
+    partition::{library_seed, spawn_seed},
+};
+
+/// Which random creatures enter the world besides the ones born in it, and where.
+///
+/// Every strategy spawns up to `creature_generation_rate` creatures per tick.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub enum SpawnStrategy {
+    /// Random creatures at random positions, every tick
+    #[default]
+    Continuous,
+    /// Random creatures during the first ticks only, afterwards the world is left to itself
+    InitialSeeding { ticks: u64 },
+    /// Random creatures only while fewer creatures with a brain are alive
+    PopulationFloor(usize),
+    /// Random creatures, placed within the regions only
+    Regions(Vec<SpawnRegion>),
+    /// Mutants of the genomes with the most living carriers instead of random creatures
+    TopGenomeMutants { genomes: usize },
+}
+
+impl SpawnStrategy {
+    pub fn name(&self) -> String {
+        match self {
+            SpawnStrategy::Continuous => "continuous".to_string(),
+            SpawnStrategy::InitialSeeding { ticks } => format!("first {ticks} ticks only"),
+            SpawnStrategy::PopulationFloor(floor) => format!("below {floor} creatures"),
+            SpawnStrategy::Regions(regions) => format!("in {} regions", regions.len()),
+            SpawnStrategy::TopGenomeMutants { genomes } => {
+                format!("mutants of the top {genomes} genomes")
+            }
+        }
+    }
+
+    /// Creatures to place this tick, their positions may turn out to be taken.
+    pub(super) fn generate(&self, world: &World) -> Vec<(Position, Creature)> {
+        let count = match self {
+            SpawnStrategy::InitialSeeding { ticks } if world.current_tick > *ticks => 0,
+            SpawnStrategy::PopulationFloor(floor) if world.population() >= *floor => 0,
+            _ => world.settings.creature_generation_rate as usize,
+        };
+        let parents = match self {
+            SpawnStrategy::TopGenomeMutants { genomes } if count > 0 => world.top_genomes(*genomes),
+            _ => Vec::new(),
+        };
+        let regions: &[SpawnRegion] = match self {
+            SpawnStrategy::Regions(regions) => regions,
+            _ => &[],
+        };
+
+        (0..count)
+            .into_par_iter()
+            .filter_map(|index| {
+                let mut rng = fastrand::Rng::with_seed(spawn_seed(
+                    world.settings.seed,
+                    world.current_tick,
+                    index,
+                ));
+                let position = match rng.choice(regions) {
+                    Some(region) => region.random_position(world.width, world.height, &mut rng)?,
+                    None => Position::randomize(world.width, world.height, &mut rng),
+                };
+                let rotation = CardinalDirection::randomize(&mut rng);
+                let genome = match rng.choice(&parents) {
+                    Some(parent) => parent.mutant(world.settings.mutation_rate, &mut rng),
+                    None => Genome::randomize(&mut rng),
+                };
+                Some((
+                    position,
+                    Creature::new(world.current_tick, rotation, Some(genome)),
+                ))
+            })
+            .collect()
+    }
+}
+
+/// Rectangle of tiles random creatures may be placed in.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct SpawnRegion {
+    pub x: usize,
+    pub y: usize,
+    pub width: usize,
+    pub height: usize,
+}
+
+impl SpawnRegion {
+    /// Random tile of the region, `None` if no part of it lies within the world.
+    fn random_position(
+        &self,
+        world_width: usize,
+        world_height: usize,
+        rng: &mut fastrand::Rng,
+    ) -> Option<Position> {
+        let columns = self.x..(self.x.saturating_add(self.width)).min(world_width);
+        let rows = self.y..(self.y.saturating_add(self.height)).min(world_height);
+        if columns.is_empty() || rows.is_empty() {
+            return None;
+        }
+        Some(Position {
+            x: rng.usize(columns),
+            y: rng.usize(rows),
+        })
+    }
+}
+
+/// Genomes saved from one world to seed another one with.
+#[derive(Clone, Default, Serialize, Deserialize)]
+pub struct GenomeLibrary {
+    pub genomes: Vec<Genome>,
+}
+
+impl GenomeLibrary {
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<(), bincode::Error> {
+        let file = BufWriter::new(File::create(path)?);
+        bincode::serialize_into(file, self)
+    }
+
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, bincode::Error> {
+        let file = BufReader::new(File::open(path)?);
+        bincode::deserialize_from(file)
+    }
+}
+
+impl World {
+    /// Living creatures with a brain
+    fn population(&self) -> usize {
+        self.creatures
+            .values()
+            .filter(|creature| !creature.is_membrane())
+            .count()
+    }
+
+    /// Genomes with the most living carriers, most common first.
+    ///
+    /// Genomes count as the same if their brains do, the body genes are taken from one of the carriers.
+    fn top_genomes(&self, count: usize) -> Vec<Genome> {
+        let mut carriers = group_by_network(
+            self.creatures
+                .values()
+                .filter_map(|creature| creature.genome.as_ref())
+                .map(|genome| (&genome.brain, genome)),
+        );
+        carriers.sort_by_key(|(brain, carriers)| {
+            (std::cmp::Reverse(carriers.len()), brain.genome_id())
+        });
+        carriers
+            .into_iter()
+            .take(count)
+            .map(|(_, carriers)| carriers[0].clone())
+            .collect()
+    }
+
+    /// Library of the `count` genomes with the most living carriers.
+    pub fn genome_library(&self, count: usize) -> GenomeLibrary {
+        GenomeLibrary {
+            genomes: self.top_genomes(count),
+        }
+    }
+
+    /// Places `copies` creatures of every genome in the library at random positions.
+    ///
+    /// They enter the world with the random spawns of the next tick, so recordings pick them up like those.
+    pub fn seed_from_library(&mut self, library: &GenomeLibrary, copies: usize) {
+        for (index, genome) in library.genomes.iter().enumerate() {
+            let seed = library_seed(self.settings.seed, self.current_tick, index);
+            let mut rng = fastrand::Rng::with_seed(seed);
+            for _ in 0..copies {
+                let creature = Creature::new(
+                    self.current_tick + 1,
+                    CardinalDirection::randomize(&mut rng),
+                    Some(genome.clone()),
+                );
+                let position = Position::randomize(self.width, self.height, &mut rng);
+                self.queued_spawns.push((position, creature));
+            }
+        }
+    }
+}
@@ -0,0 +1,111 @@
+use std::{collections::HashMap, sync::Arc};
+
+use serde::{Deserialize, Serialize};
+
+use super::neural_network::{NeuralNetwork, group_by_network};
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct Species {
+    pub id: u64,
+    pub born: u64,
+    pub population: usize,
+    pub representative: Arc<NeuralNetwork>,
+}
+
+/// Result of one clustering pass
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct SpeciesCensus {
+    pub tick: u64,
+    pub alive: usize,
+    pub births: usize,
+    pub extinctions: usize,
+}
+
+/// Groups genomes into species by their genetic distance to a representative of each species.
+#[derive(Clone, Default, Serialize, Deserialize)]
+pub struct SpeciesTracker {
+    species: Vec<Species>,
+    next_species_id: u64,
+    history: Vec<SpeciesCensus>,
+}
+
+impl SpeciesTracker {
+    /// Assigns every brain to the first species whose representative is closer than `threshold`,
+    /// founding new species where none fits. Returns the species ID for each member, given along with its brain.
+    ///
+    /// Species left without members go extinct, the others get one of their current members as new representative.
+    pub fn cluster<'a, T>(
+        &mut self,
+        tick: u64,
+        threshold: f32,
+        brains: impl IntoIterator<Item = (&'a Arc<NeuralNetwork>, T)>,
+    ) -> Vec<(T, u64)> {
+        // Sorted for a stable assignment, HashMap order would make it depend on the hasher's seed
+        let mut genomes = group_by_network(brains);
+        genomes.sort_by_key(|(brain, _)| brain.genome_id());
+
+        for species in &mut self.species {
+            species.population = 0;
+        }
+        let mut new_representatives: HashMap<u64, Arc<NeuralNetwork>> = HashMap::new();
+        let mut assignment = Vec::new();
+        let mut births = 0;
+
+        for (brain, members) in genomes {
+            let existing = self
+                .species
+                .iter()
+                .position(|species| species.representative.distance(brain) < threshold);
+            let index = match existing {
+                Some(index) => index,
+                None => {
+                    births += 1;
+                    self.species.push(Species {
+                        id: self.next_species_id,
+                        born: tick,
+                        population: 0,
+                        representative: brain.clone(),
+                    });
+                    self.next_species_id += 1;
+                    self.species.len() - 1
+                }
+            };
+            let species = &mut self.species[index];
+            species.population += members.len();
+            new_representatives
+                .entry(species.id)
+                .or_insert_with(|| brain.clone());
+            assignment.extend(members.into_iter().map(|member| (member, species.id)));
+        }
+
+        let before = self.species.len();
+        self.species.retain(|species| species.population > 0);
+        let extinctions = before - self.species.len();
+        for species in &mut self.species {
+            if let Some(representative) = new_representatives.remove(&species.id) {
+                species.representative = representative;
+            }
+        }
+
+        self.history.push(SpeciesCensus {
+            tick,
+            alive: self.species.len(),
+            births,
+            extinctions,
+        });
+
+        assignment
+    }
+
+    /// The most populous species, largest first
+    pub fn top(&self, count: usize) -> Vec<Species> {
+        let mut species = self.species.clone();
+        species.sort_unstable_by(|a, b| b.population.cmp(&a.population).then(a.id.cmp(&b.id)));
+        species.truncate(count);
+        species
+    }
+
+    pub fn history(&self) -> &[SpeciesCensus] {
+        &self.history
+    }
+}
@@ -0,0 +1,67 @@
+use std::collections::HashMap;
+
+use serde::{Deserialize, Serialize};
+
+use super::{
+    DeathCause, Position,
+    heatmap::{HeatmapLayer, Heatmaps},
+    observer::{WorldEvent, WorldObserver},
+};
+
+/// Death counts and heatmaps, collected from the events of the world they observe.
+#[derive(Clone, Default, Serialize, Deserialize)]
+pub struct Statistics {
+    death_counts: HashMap<DeathCause, u64>,
+    heatmaps: Heatmaps,
+}
+
+impl Statistics {
+    pub fn new(width: usize, height: usize) -> Self {
+        Self {
+            death_counts: HashMap::new(),
+            heatmaps: Heatmaps::new(width, height, None),
+        }
+    }
+
+    pub fn death_counts(&self) -> &HashMap<DeathCause, u64> {
+        &self.death_counts
+    }
+
+    pub fn heatmap_window(&self) -> Option<u64> {
+        self.heatmaps.window()
+    }
+
+    pub fn set_heatmap_window(&mut self, window: Option<u64>) {
+        self.heatmaps.set_window(window);
+    }
+
+    pub fn heatmap(&self, layer: HeatmapLayer, tick: u64) -> Option<Vec<f32>> {
+        self.heatmaps.intensities(layer, tick)
+    }
+
+    /// Presence is not an event, so the world reports its creatures after every tick.
+    pub fn record_presence(&mut self, position: &Position, energy: u16, tick: u64) {
+        self.heatmaps.record_presence(position, energy, tick);
+    }
+}
+
+impl WorldObserver for Statistics {
+    fn on_event(&mut self, tick: u64, event: &WorldEvent) {
+        match event {
+            WorldEvent::Spawned { position, .. } | WorldEvent::DnaCopied { position, .. } => {
+                self.heatmaps.record_birth(position, tick);
+            }
+            WorldEvent::Died {
+                position, cause, ..
+            } => {
+                *self.death_counts.entry(*cause).or_default() += 1;
+                self.heatmaps.record_death(position, *cause, tick);
+            }
+            WorldEvent::Ate { position, .. } => self.heatmaps.record_meal(position, tick),
+            WorldEvent::FoodGrew { position, amount } => {
+                self.heatmaps.record_regrowth(position, *amount, tick);
+            }
+            _ => (),
+        }
+    }
+}
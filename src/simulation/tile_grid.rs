@@ -0,0 +1,102 @@
+use std::{ops::Range, sync::Arc};
+
+use serde::{Deserialize, Serialize};
+
+use super::{Position, Tile};
+
+/// Edge length in tiles of the square chunks the grid is stored in
+pub const CHUNK_SIZE: usize = 64;
+
+/// The world's tiles, stored in square chunks that are shared between clones.
+///
+/// Cloning only bumps a reference count per chunk, a chunk is copied the first time it is written to
+/// while a clone still refers to it. This keeps snapshots cheap, as only a handful of chunks change per tick.
+///
+/// Chunks are active while food can still grow on them. Writing to a chunk activates it, `settle` deactivates
+/// the ones where every ground tile is fully grown.
+#[derive(Clone, Default, Serialize, Deserialize)]
+pub struct TileGrid {
+    width: usize,
+    height: usize,
+    chunks_per_row: usize,
+    chunks: Vec<Arc<Vec<Tile>>>,
+    active: Vec<bool>,
+}
+
+impl TileGrid {
+    pub fn new(width: usize, height: usize, tile: Tile) -> Self {
+        let chunks_per_row = width.div_ceil(CHUNK_SIZE);
+        let chunk_count = chunks_per_row * height.div_ceil(CHUNK_SIZE);
+        // All chunks start out as the same one, they are copied apart as they get written to
+        let chunk = Arc::new(vec![tile; CHUNK_SIZE * CHUNK_SIZE]);
+        let mut grid = Self {
+            width,
+            height,
+            chunks_per_row,
+            chunks: vec![chunk; chunk_count],
+            active: vec![true; chunk_count],
+        };
+        grid.settle();
+        grid
+    }
+
+    /// The position must be within bounds.
+    pub fn get(&self, position: &Position) -> &Tile {
+        let (chunk, index) = self.locate(position);
+        &self.chunks[chunk][index]
+    }
+
+    /// The position must be within bounds.
+    pub fn get_mut(&mut self, position: &Position) -> &mut Tile {
+        let (chunk, index) = self.locate(position);
+        self.active[chunk] = true;
+        &mut Arc::make_mut(&mut self.chunks[chunk])[index]
+    }
+
+    /// The position must be within bounds.
+    pub fn is_active(&self, position: &Position) -> bool {
+        self.active[self.locate(position).0]
+    }
+
+    /// Column and row ranges of every active chunk
+    pub fn active_chunks(&self) -> impl Iterator<Item = (Range<usize>, Range<usize>)> + '_ {
+        self.active
+            .iter()
+            .enumerate()
+            .filter(|(_, active)| **active)
+            .map(|(chunk, _)| {
+                let x = chunk % self.chunks_per_row * CHUNK_SIZE;
+                let y = chunk / self.chunks_per_row * CHUNK_SIZE;
+                (
+                    x..(x + CHUNK_SIZE).min(self.width),
+                    y..(y + CHUNK_SIZE).min(self.height),
+                )
+            })
+    }
+
+    /// Deactivates the chunks nothing can grow on anymore.
+    pub fn settle(&mut self) {
+        for (chunk, active) in self.chunks.iter().zip(&mut self.active) {
+            // Tiles past the edge of the world are never written and keep their initial value
+            *active = *active && chunk.iter().any(|tile| tile.can_grow());
+        }
+    }
+
+    /// All tiles row by row, the order of a flat `y * width + x` layout.
+    pub fn iter(&self) -> impl Iterator<Item = &Tile> + '_ {
+        (0..self.height).flat_map(move |y| {
+            (0..self.chunks_per_row).flat_map(move |chunk_x| {
+                let chunk = &self.chunks[y / CHUNK_SIZE * self.chunks_per_row + chunk_x];
+                let start = y % CHUNK_SIZE * CHUNK_SIZE;
+                let columns = (self.width - chunk_x * CHUNK_SIZE).min(CHUNK_SIZE);
+                &chunk[start..start + columns]
+            })
+        })
+    }
+
+    fn locate(&self, position: &Position) -> (usize, usize) {
+        let chunk = position.y / CHUNK_SIZE * self.chunks_per_row + position.x / CHUNK_SIZE;
+        let index = position.y % CHUNK_SIZE * CHUNK_SIZE + position.x % CHUNK_SIZE;
+        (chunk, index)
+    }
+}
@@ -1,31 +1,133 @@
-use std::{collections::HashMap, ops::Mul, time::Instant};
+use std::{
+    ops::Mul,
+    sync::{Arc, Mutex},
+    time::Instant,
+};
 
 use iced::{
-    Element, Length, Point, Rectangle, Renderer, Size, Subscription, Task, Theme,
+    Element, Length, Point, Rectangle, Renderer, Size, Subscription, Task, Theme, mouse,
     widget::{
-        Canvas,
+        Canvas, button,
         canvas::{self, Frame},
-        column, image, text,
+        column, image, row, slider, text,
     },
     window,
 };
 use rayon::iter::{IntoParallelRefIterator, ParallelIterator};
 use tokio_stream::wrappers::ReceiverStream;
 
-use crate::simulation::{Creature, Position, Snapshot, Tile, World, WorldSettings};
+use crate::export::{FrameExporter, TimelapseFormat};
+use crate::simulation::{
+    BackgroundBlock, BackgroundCache, CreatureLook, DeathCause, Position, Snapshot, Tile, World,
+    WorldSettings,
+    catastrophe::{Catastrophe, CatastropheKind},
+    clock::Cycle,
+    dirty::DirtyRegions,
+    food::GrowthModel,
+    heatmap::HeatmapLayer,
+    observer::WorldEvent,
+    pheromone::PHEROMONE_KINDS,
+    replay::{Replay, ReplayPlayer, ReplayRecorder},
+    rewind::RewindBuffer,
+    spawn::{GenomeLibrary, SpawnRegion, SpawnStrategy},
+};
+
+const REPLAY_PATH: &str = "replay.bin";
+const REWIND_INTERVAL: u64 = 500;
+const REWIND_CAPACITY: usize = 20;
+const FRAME_EXPORT_DIRECTORY: &str = "frames";
+const FRAME_EXPORT_INTERVAL: u64 = 100;
+const DEFAULT_HEATMAP_WINDOW: u64 = 1000;
+const GENOME_LIBRARY_PATH: &str = "genomes.bin";
+/// Genomes with the most carriers that are saved to the library
+const GENOME_LIBRARY_SIZE: usize = 10;
+/// Creatures placed per genome when seeding from the library
+const GENOME_LIBRARY_COPIES: usize = 20;
+const SEEDING_TICKS: u64 = 1000;
+const POPULATION_FLOOR: usize = 500;
+const MUTANT_GENOMES: usize = 5;
+/// Rate of logistic regrowth, a fully grazed tile takes about 150 ticks to grow back
+const LOGISTIC_RATE: f32 = 0.05;
+/// Food grows back faster and moving gets cheaper in summer, while lava burns hotter
+const SEASONS: Cycle = Cycle {
+    period: 2000,
+    food: 0.8,
+    movement: -0.5,
+    hazard: 0.5,
+};
+/// Seasons that only vary food, regrowth swings between a fifth and nearly twice its average
+const FOOD_SEASONS: Cycle = Cycle {
+    movement: 0.0,
+    hazard: 0.0,
+    ..SEASONS
+};
+/// Food grows back during the day and moving costs more at night
+const DAYS: Cycle = Cycle {
+    period: 200,
+    food: 1.0,
+    movement: -0.5,
+    hazard: 0.0,
+};
+
+const DEFAULT_CATASTROPHE_RADIUS: u16 = 20;
 
 #[derive(Debug)]
 pub enum Message {
     UpdateUi(Snapshot),
     Tick,
-    Allocated(Result<image::Allocation, image::Error>),
+    Allocated(Vec<Result<image::Allocation, image::Error>>),
+    CyclePheromoneOverlay,
+    CycleHeatmap,
+    SetHeatmapWindow(u32),
+    ExportLineage,
+    ToggleRecording,
+    LoadReplay,
+    SeekReplay(u32),
+    SelectCheckpoint(u32),
+    Fork,
+    SetFoodRegenRate(u16),
+    SetCreatureGenerationRate(u16),
+    SetMutationRate(f32),
+    CycleSpawnStrategy,
+    CycleGrowthModel,
+    CycleSeasons,
+    ToggleDays,
+    SetLavaHeat(u16),
+    SaveGenomeLibrary,
+    SeedFromGenomeLibrary,
+    ToggleFrameExport,
+    CycleTimelapseFormat,
+    CycleCatastrophe,
+    SetCatastropheRadius(u16),
+    BoardClicked(Position),
 }
 
 pub struct UI {
-    allocation: Option<image::Allocation>,
-    placeholder: image::Handle,
+    /// Keeps the latest uploaded background blocks in the renderer until they are drawn
+    allocations: Vec<image::Allocation>,
     world: Option<World>,
     snapshot: Snapshot,
+    background: BackgroundCache,
+    pheromone_overlay: Option<u8>,
+    /// Index into `HeatmapLayer::ALL` of the layer blended over the board
+    heatmap: Option<usize>,
+    heatmap_window: u64,
+    /// Event counts of the last completed tick, filled by an observer on the world
+    activity: Arc<Mutex<TickActivity>>,
+    /// Set while the live world is being recorded
+    recording: Arc<Mutex<Option<Replay>>>,
+    /// Replaces the live world once a recording is loaded
+    replay: Option<ReplayPlayer>,
+    rewind: RewindBuffer,
+    selected_checkpoint: usize,
+    /// Settings the next fork will run with
+    fork_settings: WorldSettings,
+    /// Set while frames are being written to disk
+    frame_export: Option<FrameExporter>,
+    timelapse_format: Option<TimelapseFormat>,
+    /// What clicking on the board lets strike the live world
+    catastrophe: Option<CatastropheKind>,
+    catastrophe_radius: u16,
     width: u32,
     height: u32,
 }
@@ -34,22 +136,36 @@ impl UI {
     pub fn boot() -> (Self, Task<Message>) {
         let width = 1000u32;
         let height = 1000u32;
-        let pixels = vec![255; width as usize * height as usize * 4];
 
         let settings = WorldSettings {
-            creature_generation_rate: 3,
-            food_regen_rate: 30,
+            seed: fastrand::u64(..),
+            ..WorldSettings::default()
         };
-        let world = World::new(1000, 1000, Tile::Lava, settings);
+        let mut world = World::new(1000, 1000, Tile::Lava, settings.clone());
+        world.set_heatmap_window(Some(DEFAULT_HEATMAP_WINDOW));
 
-        let ui = Self {
-            allocation: None,
-            world: Some(world),
+        let mut ui = Self {
+            allocations: Vec::new(),
+            world: None,
             snapshot: Snapshot::default(),
-            placeholder: image::Handle::from_rgba(width, height, pixels),
+            background: BackgroundCache::default(),
+            pheromone_overlay: None,
+            heatmap: None,
+            heatmap_window: DEFAULT_HEATMAP_WINDOW,
+            activity: Arc::new(Mutex::new(TickActivity::default())),
+            recording: Arc::new(Mutex::new(None)),
+            replay: None,
+            rewind: RewindBuffer::new(REWIND_INTERVAL, REWIND_CAPACITY),
+            selected_checkpoint: 0,
+            fork_settings: settings,
+            frame_export: None,
+            timelapse_format: Some(TimelapseFormat::Gif),
+            catastrophe: None,
+            catastrophe_radius: DEFAULT_CATASTROPHE_RADIUS,
             width,
             height,
         };
+        ui.run(world);
 
         // let (send, recv) = tokio::sync::mpsc::channel(1);
 
@@ -86,45 +202,585 @@ impl UI {
                 Task::none()
             }
             Message::Tick => {
+                let heatmap = self.heatmap_layer();
+                // Replays jump around, so they repaint the whole background
+                let mut dirty = DirtyRegions::default();
                 if let Some(world) = &mut self.world {
                     world.tick();
-                    self.snapshot = world.snapshot();
+                    self.rewind.record(world);
+                    dirty = world.take_dirty();
+                    self.snapshot = snapshot(world, heatmap);
+                } else if let Some(player) = &mut self.replay
+                    && player.step()
+                {
+                    self.snapshot = snapshot(player.world(), heatmap);
+                }
+
+                if let Some(exporter) = &mut self.frame_export
+                    && let Err(err) = exporter.capture(&self.snapshot)
+                {
+                    eprintln!(
+                        "Failed to export tick {}: {err}",
+                        self.snapshot.current_tick()
+                    );
                 }
 
                 // Do not parallelize the image building process - it's slower than single-threaded
 
-                self.snapshot.background_upload().map(Message::Allocated)
+                self.snapshot
+                    .background_upload(&mut self.background, &dirty, self.pheromone_overlay)
+                    .map(Message::Allocated)
+            }
+            Message::Allocated(results) => {
+                let mut allocations = Vec::with_capacity(results.len());
+                for result in results {
+                    match result {
+                        Ok(allocation) => allocations.push(allocation),
+                        Err(err) => {
+                            eprintln!("{err}");
+                            return Task::none();
+                        }
+                    }
+                }
+                self.allocations = allocations;
+                Task::done(Message::Tick)
+            }
+            Message::CyclePheromoneOverlay => {
+                self.pheromone_overlay = match self.pheromone_overlay {
+                    None => Some(0),
+                    Some(kind) if kind + 1 < PHEROMONE_KINDS => Some(kind + 1),
+                    Some(_) => None,
+                };
+                if let Some(exporter) = &mut self.frame_export {
+                    exporter.set_pheromone_overlay(self.pheromone_overlay);
+                }
+
+                Task::none()
+            }
+            Message::CycleHeatmap => {
+                self.heatmap = match self.heatmap {
+                    None => Some(0),
+                    Some(index) if index + 1 < HeatmapLayer::ALL.len() => Some(index + 1),
+                    Some(_) => None,
+                };
+
+                Task::none()
+            }
+            Message::SetHeatmapWindow(window) => {
+                self.heatmap_window = window as u64;
+                if let Some(world) = &mut self.world {
+                    world.set_heatmap_window(Some(self.heatmap_window));
+                }
+
+                Task::none()
+            }
+            Message::ExportLineage => {
+                if let Some(world) = &self.world {
+                    let path = format!("lineage_{}.nwk", world.current_tick());
+                    if let Err(err) = std::fs::write(&path, world.lineage().to_newick()) {
+                        eprintln!("Failed to write {path}: {err}");
+                    }
+                }
+
+                Task::none()
+            }
+            Message::ToggleRecording => {
+                if let Some(world) = &self.world {
+                    let mut recording = self.recording.lock().expect("Recording panicked");
+                    match recording.take() {
+                        None => *recording = Some(Replay::new(world)),
+                        Some(replay) => save_replay(&replay),
+                    }
+                }
+
+                Task::none()
+            }
+            Message::LoadReplay => {
+                match Replay::load(REPLAY_PATH) {
+                    Ok(replay) => {
+                        let player = ReplayPlayer::new(replay);
+                        self.snapshot = snapshot(player.world(), self.heatmap_layer());
+                        self.replay = Some(player);
+                        self.world = None;
+                    }
+                    Err(err) => eprintln!("Failed to read {REPLAY_PATH}: {err}"),
+                }
+
+                Task::none()
+            }
+            Message::SeekReplay(tick) => {
+                if let Some(player) = &mut self.replay {
+                    player.seek(tick as u64);
+                    self.snapshot = snapshot(player.world(), self.heatmap_layer());
+                }
+
+                Task::none()
+            }
+            Message::SelectCheckpoint(index) => {
+                self.selected_checkpoint = index as usize;
+
+                Task::none()
             }
-            Message::Allocated(result) => match result {
-                Err(err) => {
-                    eprintln!("{err}");
-                    Task::none()
+            Message::Fork => {
+                if let Some(mut world) = self.rewind.fork(self.selected_checkpoint) {
+                    // The recording can't follow the jump back, keep what it has so far
+                    if let Some(replay) = self.recording.lock().expect("Recording panicked").take()
+                    {
+                        save_replay(&replay);
+                    }
+                    world.set_settings(self.fork_settings.clone());
+                    self.snapshot = snapshot(&world, self.heatmap_layer());
+                    self.background.invalidate();
+                    self.run(world);
                 }
-                Ok(allocation) => {
-                    self.allocation = Some(allocation);
-                    Task::done(Message::Tick)
+
+                Task::none()
+            }
+            Message::SetFoodRegenRate(rate) => {
+                self.fork_settings.food_regen_rate = rate;
+
+                Task::none()
+            }
+            Message::SetCreatureGenerationRate(rate) => {
+                self.fork_settings.creature_generation_rate = rate;
+
+                Task::none()
+            }
+            Message::SetMutationRate(rate) => {
+                self.fork_settings.mutation_rate = rate;
+
+                Task::none()
+            }
+            Message::CycleSpawnStrategy => {
+                self.fork_settings.spawn_strategy = next_spawn_strategy(
+                    &self.fork_settings.spawn_strategy,
+                    self.width as usize,
+                    self.height as usize,
+                );
+
+                Task::none()
+            }
+            Message::CycleGrowthModel => {
+                let growth = &mut self.fork_settings.food_growth;
+                growth.model = match growth.model {
+                    GrowthModel::Uniform => GrowthModel::Logistic {
+                        rate: LOGISTIC_RATE,
+                    },
+                    GrowthModel::Logistic { .. } => GrowthModel::Spread,
+                    GrowthModel::Spread => GrowthModel::Uniform,
+                };
+
+                Task::none()
+            }
+            Message::CycleSeasons => {
+                let seasons = &mut self.fork_settings.clock.seasons;
+                *seasons = match seasons {
+                    None => Some(FOOD_SEASONS),
+                    Some(cycle) if *cycle == FOOD_SEASONS => Some(SEASONS),
+                    Some(_) => None,
+                };
+
+                Task::none()
+            }
+            Message::ToggleDays => {
+                let days = &mut self.fork_settings.clock.days;
+                *days = match days {
+                    Some(_) => None,
+                    None => Some(DAYS),
+                };
+
+                Task::none()
+            }
+            Message::SetLavaHeat(heat) => {
+                self.fork_settings.lava_heat = heat;
+
+                Task::none()
+            }
+            Message::SaveGenomeLibrary => {
+                if let Some(world) = &self.world
+                    && let Err(err) = world
+                        .genome_library(GENOME_LIBRARY_SIZE)
+                        .save(GENOME_LIBRARY_PATH)
+                {
+                    eprintln!("Failed to write {GENOME_LIBRARY_PATH}: {err}");
+                }
+
+                Task::none()
+            }
+            Message::SeedFromGenomeLibrary => {
+                if let Some(world) = &mut self.world {
+                    match GenomeLibrary::load(GENOME_LIBRARY_PATH) {
+                        Ok(library) => world.seed_from_library(&library, GENOME_LIBRARY_COPIES),
+                        Err(err) => eprintln!("Failed to read {GENOME_LIBRARY_PATH}: {err}"),
+                    }
+                }
+
+                Task::none()
+            }
+            Message::ToggleFrameExport => {
+                match self.frame_export.take() {
+                    Some(exporter) => match exporter.finish() {
+                        Ok(Some(path)) => println!("Time-lapse written to {}", path.display()),
+                        Ok(None) => (),
+                        Err(err) => eprintln!("Failed to write the time-lapse: {err}"),
+                    },
+                    None => {
+                        match FrameExporter::new(
+                            FRAME_EXPORT_DIRECTORY,
+                            FRAME_EXPORT_INTERVAL,
+                            self.timelapse_format,
+                        ) {
+                            Ok(mut exporter) => {
+                                exporter.set_pheromone_overlay(self.pheromone_overlay);
+                                self.frame_export = Some(exporter);
+                            }
+                            Err(err) => {
+                                eprintln!("Failed to create {FRAME_EXPORT_DIRECTORY}: {err}")
+                            }
+                        }
+                    }
                 }
-            },
+
+                Task::none()
+            }
+            Message::CycleTimelapseFormat => {
+                self.timelapse_format = match self.timelapse_format {
+                    None => Some(TimelapseFormat::Gif),
+                    Some(TimelapseFormat::Gif) => Some(TimelapseFormat::Apng),
+                    Some(TimelapseFormat::Apng) => None,
+                };
+
+                Task::none()
+            }
+            Message::CycleCatastrophe => {
+                let next = match self.catastrophe {
+                    Some(kind) => CatastropheKind::ALL
+                        .iter()
+                        .position(|other| *other == kind)
+                        .map_or(0, |index| index + 1),
+                    None => 0,
+                };
+                self.catastrophe = CatastropheKind::ALL.get(next).copied();
+
+                Task::none()
+            }
+            Message::SetCatastropheRadius(radius) => {
+                self.catastrophe_radius = radius;
+
+                Task::none()
+            }
+            Message::BoardClicked(position) => {
+                if let Some(world) = &mut self.world
+                    && let Some(kind) = self.catastrophe
+                {
+                    world.trigger(Catastrophe {
+                        kind,
+                        x: position.x,
+                        y: position.y,
+                        radius: self.catastrophe_radius as usize,
+                    });
+                }
+
+                Task::none()
+            }
         }
     }
 
+    fn heatmap_layer(&self) -> Option<HeatmapLayer> {
+        self.heatmap.map(|index| HeatmapLayer::ALL[index])
+    }
+
+    /// Makes `world` the live world and hooks the UI's observers up to it.
+    fn run(&mut self, mut world: World) {
+        world.add_observer(TickActivity::observer(self.activity.clone()));
+        world.add_observer(ReplayRecorder::new(self.recording.clone()));
+        self.world = Some(world);
+    }
+
     pub fn view(&self) -> Element<'_, Message> {
-        let handle = self
-            .allocation
-            .as_ref()
-            .map(|allocation| allocation.handle())
-            .unwrap_or_else(|| &self.placeholder);
         column![
             text!("Current Tick: {}", self.snapshot.current_tick()),
             text!("Creatures_alive: {}", self.snapshot.creature_count()),
-            image(handle)
+            text!("Membranes: {}", self.snapshot.membrane_count()),
+            text!(
+                "Environment: food x{:.2}, movement costs x{:.2}, lava heat x{:.2}",
+                self.snapshot.environment().food,
+                self.snapshot.environment().movement,
+                self.snapshot.environment().hazard
+            ),
+            self.activity_view(),
+            text!(
+                "Deaths: {} old age, {} starvation, {} terrain, {} predation, {} decayed membranes, {} catastrophes",
+                self.snapshot.deaths(DeathCause::OldAge),
+                self.snapshot.deaths(DeathCause::Starvation),
+                self.snapshot.deaths(DeathCause::Terrain),
+                self.snapshot.deaths(DeathCause::Predation),
+                self.snapshot.deaths(DeathCause::Decay),
+                self.snapshot.deaths(DeathCause::Catastrophe)
+            ),
+            text(match self.snapshot.most_successful_genome() {
+                Some((genome, descendants)) => {
+                    format!("Most successful genome: {genome:x} ({descendants} descendants)")
+                }
+                None => "Most successful genome: none yet".to_string(),
+            }),
+            row![
+                button("Export lineage").on_press(Message::ExportLineage),
+                button("Pheromones").on_press(Message::CyclePheromoneOverlay),
+                text(match self.pheromone_overlay {
+                    Some(kind) => format!(
+                        "Showing kind {kind} ({} active chunks)",
+                        self.snapshot.active_pheromone_chunks(kind)
+                    ),
+                    None => "Hidden".to_string(),
+                }),
+                button("Heatmap").on_press(Message::CycleHeatmap),
+                text(match self.heatmap_layer() {
+                    Some(layer) => format!("Showing {}", layer.name()),
+                    None => "Hidden".to_string(),
+                }),
+                text!("Window {} ticks", self.heatmap_window),
+                slider(
+                    100..=10_000,
+                    self.heatmap_window as u32,
+                    Message::SetHeatmapWindow
+                )
+                .step(100u32),
+            ]
+            .spacing(10),
+            self.replay_view(),
+            self.rewind_view(),
+            self.frame_export_view(),
+            self.catastrophe_view(),
+            self.species_view(),
+            Canvas::new(Board {
+                width: self.snapshot.width() as u32,
+                height: self.snapshot.height() as u32,
+                creatures: self.snapshot.creatures(),
+                background: self.background.blocks(),
+            })
                 .height(Length::Fill)
                 .width(Length::Fill)
-                .filter_method(image::FilterMethod::Nearest)
         ]
         .into()
     }
 
+    fn activity_view(&self) -> Element<'_, Message> {
+        let activity = self
+            .activity
+            .lock()
+            .expect("Activity observer panicked")
+            .clone();
+        text!(
+            "Last tick: {} births, {} deaths, {} moves, {} meals",
+            activity.births,
+            activity.deaths,
+            activity.moves,
+            activity.meals
+        )
+        .into()
+    }
+
+    fn replay_view(&self) -> Element<'_, Message> {
+        match &self.replay {
+            Some(player) => row![
+                text!(
+                    "Replay: ticks {} to {}",
+                    player.replay().first_tick(),
+                    player.replay().last_tick()
+                ),
+                slider(
+                    player.replay().first_tick() as u32..=player.replay().last_tick() as u32,
+                    player.world().current_tick() as u32,
+                    Message::SeekReplay
+                ),
+            ]
+            .spacing(10)
+            .into(),
+            None => {
+                let recording = self.recording.lock().expect("Recording panicked");
+                row![
+                    button(if recording.is_some() {
+                        "Stop and save recording"
+                    } else {
+                        "Record"
+                    })
+                    .on_press(Message::ToggleRecording),
+                    button("Load replay").on_press(Message::LoadReplay),
+                    text(match recording.as_ref() {
+                        Some(replay) => format!("Recording since tick {}", replay.first_tick()),
+                        None => format!("Not recording, replays are saved to {REPLAY_PATH}"),
+                    }),
+                ]
+                .spacing(10)
+                .into()
+            }
+        }
+    }
+
+    fn rewind_view(&self) -> Element<'_, Message> {
+        if self.world.is_none() {
+            return column![].into();
+        }
+        let timeline: Element<'_, Message> = match self.rewind.len().checked_sub(1) {
+            Some(last) => {
+                let selected = self.selected_checkpoint.min(last);
+                let tick = self
+                    .rewind
+                    .get(selected)
+                    .map(World::current_tick)
+                    .unwrap_or_default();
+                row![
+                    slider(0..=last as u32, selected as u32, Message::SelectCheckpoint),
+                    button(text!("Fork from tick {tick}")).on_press(Message::Fork),
+                ]
+                .spacing(10)
+                .into()
+            }
+            None => text!("First checkpoint at tick {REWIND_INTERVAL}").into(),
+        };
+
+        column![
+            timeline,
+            row![
+                text!("Food regrowth {}", self.fork_settings.food_regen_rate),
+                slider(
+                    0..=100,
+                    self.fork_settings.food_regen_rate,
+                    Message::SetFoodRegenRate
+                ),
+                text!(
+                    "Random spawns {}",
+                    self.fork_settings.creature_generation_rate
+                ),
+                slider(
+                    0..=20,
+                    self.fork_settings.creature_generation_rate,
+                    Message::SetCreatureGenerationRate
+                ),
+                text!("Mutation rate {:.2}", self.fork_settings.mutation_rate),
+                slider(
+                    0.0..=0.5,
+                    self.fork_settings.mutation_rate,
+                    Message::SetMutationRate
+                )
+                .step(0.01),
+                button(text!(
+                    "Spawns: {}",
+                    self.fork_settings.spawn_strategy.name()
+                ))
+                .on_press(Message::CycleSpawnStrategy),
+            ]
+            .spacing(10),
+            row![
+                button(text!(
+                    "Food growth: {}",
+                    self.fork_settings.food_growth.model.name()
+                ))
+                .on_press(Message::CycleGrowthModel),
+                button(match &self.fork_settings.clock.seasons {
+                    None => "Seasons: off",
+                    Some(cycle) if *cycle == FOOD_SEASONS => "Seasons: food only",
+                    Some(_) => "Seasons: on",
+                })
+                .on_press(Message::CycleSeasons),
+                button(if self.fork_settings.clock.days.is_some() {
+                    "Days: on"
+                } else {
+                    "Days: off"
+                })
+                .on_press(Message::ToggleDays),
+                text!("Lava heat {}", self.fork_settings.lava_heat),
+                slider(0..=20, self.fork_settings.lava_heat, Message::SetLavaHeat),
+            ]
+            .spacing(10),
+            row![
+                button("Save genomes").on_press(Message::SaveGenomeLibrary),
+                button("Seed from genomes").on_press(Message::SeedFromGenomeLibrary),
+                text!(
+                    "The top {GENOME_LIBRARY_SIZE} genomes are saved to {GENOME_LIBRARY_PATH}, seeding places {GENOME_LIBRARY_COPIES} of each"
+                ),
+            ]
+            .spacing(10),
+        ]
+        .into()
+    }
+
+    fn frame_export_view(&self) -> Element<'_, Message> {
+        let status = match &self.frame_export {
+            Some(exporter) => format!(
+                "{} frames written to {FRAME_EXPORT_DIRECTORY}",
+                exporter.frame_count()
+            ),
+            None => format!("Every {FRAME_EXPORT_INTERVAL} ticks"),
+        };
+        let format_button = match self.timelapse_format {
+            Some(TimelapseFormat::Gif) => "Time-lapse: GIF",
+            Some(TimelapseFormat::Apng) => "Time-lapse: APNG",
+            None => "Time-lapse: off",
+        };
+
+        row![
+            button(if self.frame_export.is_some() {
+                "Stop exporting frames"
+            } else {
+                "Export frames"
+            })
+            .on_press(Message::ToggleFrameExport),
+            // The format can't change while the time-lapse is being encoded
+            button(format_button).on_press_maybe(
+                self.frame_export
+                    .is_none()
+                    .then_some(Message::CycleTimelapseFormat)
+            ),
+            text(status),
+        ]
+        .spacing(10)
+        .into()
+    }
+
+    fn catastrophe_view(&self) -> Element<'_, Message> {
+        row![
+            button(text!(
+                "Clicking the board: {}",
+                self.catastrophe.map_or("nothing", |kind| kind.name())
+            ))
+            .on_press(Message::CycleCatastrophe),
+            text!("Radius {}", self.catastrophe_radius),
+            slider(
+                1..=100,
+                self.catastrophe_radius,
+                Message::SetCatastropheRadius
+            ),
+        ]
+        .spacing(10)
+        .into()
+    }
+
+    fn species_view(&self) -> Element<'_, Message> {
+        let census = match self.snapshot.species_census() {
+            Some(census) => format!(
+                "Species: {} alive, {} new, {} extinct since the last census",
+                census.alive, census.births, census.extinctions
+            ),
+            None => "Species: no census yet".to_string(),
+        };
+
+        let top_species = self.snapshot.top_species().iter().map(|species| {
+            text!(
+                "#{} since tick {}: {} creatures - {}",
+                species.id,
+                species.born,
+                species.population,
+                species.representative.describe()
+            )
+            .color(species_color(species.id))
+            .into()
+        });
+
+        column![text(census)].extend(top_species).into()
+    }
+
     pub fn subscription(&self) -> iced::Subscription<Message> {
         // window::frames().map(|_| Message::Tick)
         Subscription::none()
@@ -132,16 +788,63 @@ impl UI {
     }
 }
 
+#[derive(Default, Clone)]
+struct TickActivity {
+    births: usize,
+    deaths: usize,
+    moves: usize,
+    meals: usize,
+}
+
+impl TickActivity {
+    /// Counts events during a tick and publishes the totals to `last_tick` when it ends.
+    fn observer(last_tick: Arc<Mutex<TickActivity>>) -> impl FnMut(u64, &WorldEvent) + Send + Sync {
+        let mut current = TickActivity::default();
+        move |_tick, event| match event {
+            WorldEvent::Spawned { .. } => current.births += 1,
+            WorldEvent::Died { .. } => current.deaths += 1,
+            WorldEvent::Moved { .. } => current.moves += 1,
+            WorldEvent::Ate { .. } => current.meals += 1,
+            WorldEvent::TickEnded => {
+                *last_tick.lock().expect("UI thread panicked") = std::mem::take(&mut current);
+            }
+            _ => (),
+        }
+    }
+}
+
 pub struct Board<'a> {
     pub width: u32,
     pub height: u32,
-    pub creatures: &'a HashMap<Position, Creature>,
-    pub background: &'a image::Handle,
+    pub creatures: &'a [(Position, CreatureLook)],
+    pub background: &'a [BackgroundBlock],
 }
 
 impl<'a> canvas::Program<Message> for Board<'a> {
     type State = ();
 
+    fn update(
+        &self,
+        _state: &mut Self::State,
+        event: &canvas::Event,
+        bounds: Rectangle,
+        cursor: mouse::Cursor,
+    ) -> Option<canvas::Action<Message>> {
+        let canvas::Event::Mouse(mouse::Event::ButtonPressed(mouse::Button::Left)) = event else {
+            return None;
+        };
+        let point = cursor.position_in(bounds)?;
+        let position = Position {
+            x: (point.x / bounds.width * self.width as f32) as usize,
+            y: (point.y / bounds.height * self.height as f32) as usize,
+        };
+        if position.x >= self.width as usize || position.y >= self.height as usize {
+            return None;
+        }
+
+        Some(canvas::Action::publish(Message::BoardClicked(position)).and_capture())
+    }
+
     fn draw(
         &self,
         state: &Self::State,
@@ -162,11 +865,70 @@ impl<'a> canvas::Program<Message> for Board<'a> {
                 tile_height * self.height as f32,
             ),
         );
-        frame.draw_image(
-            Rectangle::new(Point::ORIGIN, bounds.size()),
-            self.background,
-        );
+        for BackgroundBlock { block, handle, .. } in self.background {
+            frame.draw_image(
+                Rectangle::new(
+                    Point::new(
+                        block.columns.start as f32 * tile_width,
+                        block.rows.start as f32 * tile_height,
+                    ),
+                    Size::new(
+                        block.columns.len() as f32 * tile_width,
+                        block.rows.len() as f32 * tile_height,
+                    ),
+                ),
+                handle,
+            );
+        }
+
+        for (position, look) in self.creatures {
+            frame.fill_rectangle(
+                Point::new(
+                    position.x as f32 * tile_width,
+                    position.y as f32 * tile_height,
+                ),
+                tile_size,
+                iced::Color {
+                    a: look.opacity,
+                    ..look.color.to_iced()
+                },
+            );
+        }
 
         vec![frame.into_geometry()]
     }
 }
+
+/// Snapshot of the world with the heatmap of the given layer blended over the background
+fn snapshot(world: &World, heatmap: Option<HeatmapLayer>) -> Snapshot {
+    let mut snapshot = world.snapshot();
+    snapshot.set_heatmap(heatmap.and_then(|layer| world.heatmap(layer)));
+    snapshot
+}
+
+/// Strategy the fork settings switch to from `strategy`, going through one example of each kind.
+fn next_spawn_strategy(strategy: &SpawnStrategy, width: usize, height: usize) -> SpawnStrategy {
+    match strategy {
+        SpawnStrategy::Continuous => SpawnStrategy::InitialSeeding {
+            ticks: SEEDING_TICKS,
+        },
+        SpawnStrategy::InitialSeeding { .. } => SpawnStrategy::PopulationFloor(POPULATION_FLOOR),
+        // The middle of the world
+        SpawnStrategy::PopulationFloor(_) => SpawnStrategy::Regions(vec![SpawnRegion {
+            x: width / 4,
+            y: height / 4,
+            width: width / 2,
+            height: height / 2,
+        }]),
+        SpawnStrategy::Regions(_) => SpawnStrategy::TopGenomeMutants {
+            genomes: MUTANT_GENOMES,
+        },
+        SpawnStrategy::TopGenomeMutants { .. } => SpawnStrategy::Continuous,
+    }
+}
+
+fn save_replay(replay: &Replay) {
+    if let Err(err) = replay.save(REPLAY_PATH) {
+        eprintln!("Failed to write {REPLAY_PATH}: {err}");
+    }
+}